@@ -1,9 +1,11 @@
+use std::path::PathBuf;
 use std::sync::LazyLock;
 
 pub static ARGS: LazyLock<Args> = LazyLock::new(|| {
 	let mut out = Args::default();
 
-	for arg in std::env::args().skip(1) {
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
 		match arg.as_str() {
 			"list" => {
 				let pm_ctx = portmidi::PortMidi::new().unwrap();
@@ -11,8 +13,47 @@ pub static ARGS: LazyLock<Args> = LazyLock::new(|| {
 				devices.iter().for_each(|d| println!("[MAIN]: device {} {:?} {:?}", d.id(), d.name(), d.direction()));
 				std::process::exit(0);
 			},
+			"list-audio" => {
+				crate::audio_device::list();
+				std::process::exit(0);
+			},
 			"hmr"       => out.hmr_enable = true,
 			"logupdate" => out.log_update = true,
+			"render"    => out.render = Some(RenderArgs::default()),
+
+			"--audio-file" => out.audio_file = Some(args.next().map(PathBuf::from).unwrap_or_else(|| {
+				eprintln!("[ARGS]: --audio-file is missing a value");
+				std::process::exit(1);
+			})),
+
+			flag @ ("--audio-host" | "--audio-device" | "--audio-channels" | "--audio-rate") => {
+				let value = args.next().unwrap_or_else(|| {
+					eprintln!("[ARGS]: {flag} is missing a value");
+					std::process::exit(1);
+				});
+
+				out.audio.set(flag, &value).unwrap_or_else(|e| {
+					eprintln!("[ARGS]: bad value {value:?} for {flag}: {e}");
+					std::process::exit(1);
+				});
+			},
+
+			flag if flag.starts_with("--") => {
+				let Some(render) = out.render.as_mut() else {
+					eprintln!("[ARGS]: {flag} only goes after render");
+					std::process::exit(1);
+				};
+
+				let value = args.next().unwrap_or_else(|| {
+					eprintln!("[ARGS]: {flag} is missing a value");
+					std::process::exit(1);
+				});
+
+				render.set(flag, &value).unwrap_or_else(|e| {
+					eprintln!("[ARGS]: bad value {value:?} for {flag}: {e}");
+					std::process::exit(1);
+				});
+			},
 			_ => { },
 		}
 	}
@@ -24,4 +65,80 @@ pub static ARGS: LazyLock<Args> = LazyLock::new(|| {
 pub struct Args {
 	pub hmr_enable: bool,
 	pub log_update: bool,
+	pub render:     Option<RenderArgs>,
+	// looped in place of the input device, live and in renders
+	pub audio_file: Option<PathBuf>,
+	// overrides for the input device in audio.toml
+	pub audio:      crate::audio_device::AudioConfig,
+}
+
+// cargo run -- render --plugin v2 --preset presets/default.toml --size 1280x720 --fps 30 --duration 10 --out renders
+#[derive(Debug)]
+pub struct RenderArgs {
+	// file name of the plugin with or without the number prefix, first plugin if not set
+	pub plugin:   Option<String>,
+	pub preset:   Option<PathBuf>,
+	pub width:    u32,
+	pub height:   u32,
+	pub fps:      f32,
+	pub duration: f32,
+	// a directory of pngs, or a single video when it ends in .y4m, .avi or .gif
+	pub out:      PathBuf,
+	// tempo the render runs at like following a midi clock, and a loop length in beats of it
+	// that the end of the render gets crossfaded into the start over, overrides the duration
+	pub bpm:        Option<f32>,
+	pub loop_beats: Option<f32>,
+	// visual time and camera spin to start from, like the ones in a screenshot's .toml
+	pub start:      f32,
+	pub spin_angle: f32,
+}
+
+impl Default for RenderArgs {
+	fn default() -> Self {
+		Self {
+			plugin:   None,
+			preset:   None,
+			width:    640,
+			height:   360,
+			fps:      30.0,
+			duration: 5.0,
+			out:      PathBuf::from("renders"),
+			bpm:        None,
+			loop_beats: None,
+			start:      0.0,
+			spin_angle: 0.0,
+		}
+	}
+}
+
+impl RenderArgs {
+	fn set(&mut self, flag: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+		match flag {
+			"--plugin"   => self.plugin   = Some(String::from(value)),
+			"--preset"   => self.preset   = Some(PathBuf::from(value)),
+			"--fps"      => self.fps      = value.parse()?,
+			"--duration" => self.duration = value.parse()?,
+			"--out"      => self.out      = PathBuf::from(value),
+			"--bpm"        => self.bpm        = Some(value.parse()?),
+			"--loop-beats" => self.loop_beats = Some(value.parse()?),
+			"--start"      => self.start      = value.parse()?,
+			"--spin-angle" => self.spin_angle = value.parse()?,
+			"--size"     => {
+				let (w, h) = value.split_once('x').ok_or("expected WIDTHxHEIGHT")?;
+				self.width  = w.parse()?;
+				self.height = h.parse()?;
+			},
+			_ => return Err(format!("unknown flag {flag}").into()),
+		}
+
+		if self.width == 0 || self.height == 0 || self.fps <= 0.0 {
+			return Err("size and fps have to be above 0".into());
+		}
+
+		if self.bpm.is_some_and(|b| b <= 0.0) || self.loop_beats.is_some_and(|b| b <= 0.0) {
+			return Err("bpm and loop beats have to be above 0".into());
+		}
+
+		Ok(())
+	}
 }
@@ -0,0 +1,275 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use nannou_audio::cpal;
+use nannou_audio::cpal::traits::{DeviceTrait, HostTrait};
+use nannou_audio::Buffer;
+
+use ringbuf::traits::{Observer, Producer};
+
+use crate::audio_processor::AudioProcessor;
+
+// how often to look for an input while there isn't one, and to check the one there is still sends
+const RETRY: Duration = Duration::from_secs(2);
+
+// which input to listen to, anything not set is the system default
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+	// e.g. "ALSA" or "JACK"
+	pub host:        Option<String>,
+	// the device name or any part of it, case insensitive
+	pub device:      Option<String>,
+	pub channels:    Option<u16>,
+	pub sample_rate: Option<u32>,
+	// how the input is analysed, file only
+	pub fft:         crate::audio_processor::FftConfig,
+	// named parts of the spectrum handed to plugins, file only too
+	pub bands:       Vec<crate::audio_processor::Band>,
+	// beat detection and what it sets off, file only too
+	pub onset:       crate::onset::OnsetConfig,
+	// noise gate and agc before the fft, file only too
+	pub gain:        crate::gain::GainConfig,
+	// how left and right become the one signal that's analysed, and whether
+	// to analyse them apart as well for the two halves of the grid, file only too
+	pub downmix:     crate::audio_processor::Downmix,
+	pub stereo:      bool,
+}
+
+impl Default for AudioConfig {
+	fn default() -> Self {
+		Self {
+			host:        None,
+			device:      None,
+			channels:    None,
+			sample_rate: None,
+			fft:         Default::default(),
+			bands:       crate::audio_processor::Band::defaults(),
+			onset:       Default::default(),
+			gain:        Default::default(),
+			downmix:     Default::default(),
+			stereo:      false,
+		}
+	}
+}
+
+impl AudioConfig {
+	// a missing file is fine, the defaults are what it always used to do
+	pub fn from_file(path: impl AsRef<Path>) -> Self {
+		let Ok(s) = std::fs::read_to_string(path.as_ref()) else { return Self::default(); };
+
+		toml::from_str(&s).unwrap_or_else(|e| {
+			eprintln!("[AUDIO]: Error reading {:?}, using the default input: {e}", path.as_ref());
+			Self::default()
+		})
+	}
+
+	// anything set here wins over the other
+	pub fn or(&self, other: Self) -> Self {
+		Self {
+			host:        self.host.clone().or(other.host),
+			device:      self.device.clone().or(other.device),
+			channels:    self.channels.or(other.channels),
+			sample_rate: self.sample_rate.or(other.sample_rate),
+			fft:         other.fft,
+			bands:       other.bands,
+			onset:       other.onset,
+			gain:        other.gain,
+			downmix:     other.downmix,
+			stereo:      other.stereo,
+		}
+	}
+
+	pub fn set(&mut self, flag: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+		match flag {
+			"--audio-host"     => self.host        = Some(String::from(value)),
+			"--audio-device"   => self.device      = Some(String::from(value)),
+			"--audio-channels" => self.channels    = Some(value.parse()?),
+			"--audio-rate"     => self.sample_rate = Some(value.parse()?),
+			_ => return Err(format!("unknown flag {flag}").into()),
+		}
+		Ok(())
+	}
+}
+
+struct Input {
+	host:        nannou_audio::Host,
+	device:      nannou_audio::Device,
+	channels:    u16,
+	sample_rate: u32,
+}
+
+// the configured input, or the default one with a warning when it isn't there
+fn select(cfg: &AudioConfig) -> Result<Input, Box<dyn std::error::Error>> {
+	let host = match &cfg.host {
+		Some(name) => match cpal::available_hosts().into_iter().find(|id| id.name().eq_ignore_ascii_case(name)) {
+			Some(id) => nannou_audio::Host::from_id(id)?,
+			None     => {
+				eprintln!("[AUDIO][WARN]: host {:?} not available, using the default host", name);
+				nannou_audio::Host::new()
+			},
+		},
+		None => nannou_audio::Host::new(),
+	};
+
+	let named = cfg.device.as_ref().and_then(|name| {
+		let needle = name.to_lowercase();
+		let found  = host.input_devices().ok()?
+			.find(|d| d.name().is_ok_and(|n| n.to_lowercase().contains(&needle)));
+
+		if found.is_none() {
+			eprintln!("[AUDIO][WARN]: input device {:?} not found, using the default input", name);
+		}
+		found
+	});
+
+	let device = match named {
+		Some(device) => device,
+		None         => host.default_input_device().ok_or("no default input device")?,
+	};
+
+	let default = device.default_input_config()?;
+
+	// only ask for a layout the device says it can do
+	let supported = device.supported_input_configs()?.any(|c|
+		cfg.channels.is_none_or(|ch| c.channels() == ch)
+			&& cfg.sample_rate.is_none_or(|r| (c.min_sample_rate().0..=c.max_sample_rate().0).contains(&r)));
+
+	let (channels, sample_rate) = match supported {
+		true  => (cfg.channels.unwrap_or(default.channels()), cfg.sample_rate.unwrap_or(default.sample_rate().0)),
+		false => {
+			eprintln!("[AUDIO][WARN]: {:?} channels at {:?} Hz not supported, using {} at {} Hz",
+				cfg.channels, cfg.sample_rate, default.channels(), default.sample_rate().0);
+			(default.channels(), default.sample_rate().0)
+		},
+	};
+
+	println!("[AUDIO]: input {:?} {} channels at {} Hz", device.name().unwrap_or_default(), channels, sample_rate);
+
+	Ok(Input { host, device, channels, sample_rate })
+}
+
+struct InputModel {
+	producer: Arc<Mutex<ringbuf::HeapProd<f32>>>,
+	// set by every callback, a device sends them even when it's quiet
+	alive:    Arc<AtomicBool>,
+}
+
+fn pass_in(model: &mut InputModel, buffer: &Buffer) {
+	model.alive.store(true, Ordering::Relaxed);
+
+	// everything downstream takes left and right pairs, a mono input goes to both
+	// and anything past the first two channels is left out
+	let Ok(mut producer) = model.producer.try_lock() else { return; };
+	buffer.frames().for_each(|f| push_frame(&mut producer, f[0], *f.get(1).unwrap_or(&f[0])));
+}
+
+// left and right go in together or not at all, one on its own would swap every pair after it
+pub fn push_frame(producer: &mut ringbuf::HeapProd<f32>, left: f32, right: f32) {
+	if producer.vacant_len() >= 2 {
+		producer.push_slice(&[left, right]);
+	}
+}
+
+// a playing input stream, dropping it closes the device
+struct Opened {
+	stream:      nannou_audio::Stream<InputModel>,
+	sample_rate: usize,
+	alive:       Arc<AtomicBool>,
+}
+
+fn open(cfg: &AudioConfig, producer: &Arc<Mutex<ringbuf::HeapProd<f32>>>) -> Result<Opened, Box<dyn std::error::Error>> {
+	let input = select(cfg)?;
+	let alive = Arc::new(AtomicBool::new(false));
+
+	let in_model  = InputModel { producer: producer.clone(), alive: alive.clone() };
+	let in_stream = input.host
+		.new_input_stream(in_model)
+		.device(input.device)
+		.channels(input.channels as usize)
+		.sample_rate(input.sample_rate)
+		.capture(pass_in)
+		.build()?;
+
+	in_stream.play()?;
+
+	Ok(Opened { stream: in_stream, sample_rate: input.sample_rate as usize, alive })
+}
+
+// keeps an input stream going in the background, attaching whenever a device turns up
+// and again when the one in use stops, the fft just hears silence in between
+pub fn spawn(cfg: AudioConfig, producer: ringbuf::HeapProd<f32>, ap: Arc<Mutex<AudioProcessor>>) {
+	let producer = Arc::new(Mutex::new(producer));
+
+	std::thread::spawn(move || {
+		let mut warned = false;
+		loop {
+			let Opened { stream, sample_rate, alive } = match open(&cfg, &producer) {
+				Ok(opened) => opened,
+				Err(e)     => {
+					if !warned {
+						eprintln!("[AUDIO][WARN]: no audio input ({e}), fft stays silent, retrying every {:?}", RETRY);
+						warned = true;
+					}
+					std::thread::sleep(RETRY);
+					continue;
+				},
+			};
+
+			println!("[AUDIO]: listening");
+			warned = false;
+			ap.lock().unwrap().set_sample_rate(sample_rate);
+
+			// no callbacks since the last look means the device has gone
+			loop {
+				std::thread::sleep(RETRY);
+				if !alive.swap(false, Ordering::Relaxed) { break; }
+			}
+
+			drop(stream);
+			ap.lock().unwrap().reset();
+			eprintln!("[AUDIO][WARN]: input stopped sending, reattaching");
+		}
+	});
+}
+
+// every host and the input devices on it
+pub fn list() {
+	for id in cpal::available_hosts() {
+		println!("[AUDIO]: host {:?}", id.name());
+
+		let Ok(host) = cpal::host_from_id(id) else { continue; };
+		let Ok(devices) = host.input_devices() else { continue; };
+
+		for device in devices {
+			let name = device.name().unwrap_or_default();
+			match device.default_input_config() {
+				Ok(c)  => println!("[AUDIO]:   {:?} {} channels at {} Hz", name, c.channels(), c.sample_rate().0),
+				Err(_) => println!("[AUDIO]:   {:?}", name),
+			}
+
+			device.supported_input_configs().into_iter().flatten().for_each(|c| {
+				println!("[AUDIO]:     {} channels {}..{} Hz", c.channels(), c.min_sample_rate().0, c.max_sample_rate().0);
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ringbuf::traits::{Consumer, Split};
+
+	#[test]
+	fn frames_are_pushed_whole_or_not_at_all() {
+		let (mut prod, mut cons) = ringbuf::HeapRb::<f32>::new(5).split();
+
+		(0..3).for_each(|n| push_frame(&mut prod, n as f32, -(n as f32)));
+		assert_eq!(cons.pop_iter().collect::<Vec<_>>(), [0.0, -0.0, 1.0, -1.0]);
+
+		push_frame(&mut prod, 3.0, -3.0);
+		assert_eq!(cons.pop_iter().collect::<Vec<_>>(), [3.0, -3.0]);
+	}
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+// a whole audio file decoded up front, standing in for the input device
+pub struct AudioFile {
+	pub sample_rate: usize,
+	pub channels:    usize,
+	// interleaved like the device buffers are
+	samples:         Vec<f32>,
+}
+
+impl AudioFile {
+	// wav, flac or ogg vorbis
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+		let path = path.as_ref();
+		let mss  = MediaSourceStream::new(Box::new(std::fs::File::open(path)?), Default::default());
+
+		let mut hint = Hint::new();
+		if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+			hint.with_extension(ext);
+		}
+
+		let mut format = symphonia::default::get_probe()
+			.format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())?
+			.format;
+
+		let track       = format.default_track().ok_or("no audio track")?;
+		let track_id    = track.id;
+		let sample_rate = track.codec_params.sample_rate.ok_or("unknown sample rate")? as usize;
+		let mut channels = track.codec_params.channels.map_or(1, |c| c.count());
+		let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+		let mut samples = Vec::new();
+		loop {
+			let packet = match format.next_packet() {
+				Ok(packet) => packet,
+				Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+				Err(e) => return Err(e.into()),
+			};
+
+			if packet.track_id() != track_id { continue; }
+
+			let decoded = match decoder.decode(&packet) {
+				Ok(decoded) => decoded,
+				Err(DecodeError::DecodeError(e)) => {
+					eprintln!("[AUDIO FILE]: skipping a bad packet in {:?}: {e}", path);
+					continue;
+				},
+				Err(e) => return Err(e.into()),
+			};
+
+			channels = decoded.spec().channels.count();
+			let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+			buf.copy_interleaved_ref(decoded);
+			samples.extend_from_slice(buf.samples());
+		}
+
+		if samples.len() < channels.max(1) {
+			return Err(format!("{:?} has no samples", path).into());
+		}
+
+		println!("[AUDIO FILE]: {:?} {} Hz {} channels {:.1}s", path, sample_rate, channels,
+			samples.len() as f32 / (sample_rate * channels) as f32);
+
+		Ok(Self { sample_rate, channels, samples })
+	}
+
+	// left and right pairs of the frames from..from + frames, looping round the end
+	// like a device input, mono goes to both and anything past two channels is left out
+	pub fn frames(&self, from: usize, frames: usize) -> impl Iterator<Item = f32> + '_ {
+		let len   = self.samples.len() / self.channels;
+		let right = usize::from(self.channels > 1);
+		(from..from + frames).flat_map(move |i| {
+			let at = (i % len) * self.channels;
+			[self.samples[at], self.samples[at + right]]
+		})
+	}
+
+	// feeds the ring buffer at the pace a device would, looping forever
+	pub fn play(self, mut producer: ringbuf::HeapProd<f32>) {
+		const TICK: Duration = Duration::from_millis(10);
+
+		let start    = Instant::now();
+		let mut sent = 0;
+		loop {
+			let due = (start.elapsed().as_secs_f64() * self.sample_rate as f64) as usize;
+
+			self.frames(sent, due - sent).collect::<Vec<_>>().chunks_exact(2)
+				.for_each(|f| crate::audio_device::push_frame(&mut producer, f[0], f[1]));
+			sent = due;
+
+			std::thread::sleep(TICK);
+		}
+	}
+}
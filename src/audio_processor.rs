@@ -1,74 +1,509 @@
 use rustfft::{Fft, FftPlanner};
 use rustfft::num_complex::Complex;
 
-use std::cmp::Ordering;
+use std::f32::consts::TAU;
 use std::sync::Arc;
 
+// until an input says what it runs at
+pub const DEFAULT_SAMPLE_RATE: usize = 48000;
+
+const SILENCE_DB: f32 = -160.0;
+
+pub const NOTES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+// the range the chroma is taken over, lower bins are too wide to tell semitones apart
+// and higher up is mostly harmonics and noise
+const CHROMA_HZ: (f32, f32) = (80.0, 5000.0);
+// the chroma follows slower than the spectrum so the note doesn't flicker between chords
+const CHROMA_MS: f32 = 1000.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Window {
+	// no window at all, lots of leakage
+	None,
+	#[default]
+	Hann,
+	Hamming,
+	Blackman,
+}
+
+impl Window {
+	fn coefficients(self, size: usize) -> Vec<f32> {
+		let n = (size.max(2) - 1) as f32;
+		(0..size).map(|i| {
+			let x = TAU * i as f32 / n;
+			match self {
+				Self::None     => 1.0,
+				Self::Hann     => 0.5 - 0.5 * x.cos(),
+				Self::Hamming  => 0.54 - 0.46 * x.cos(),
+				Self::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+			}
+		}).collect()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(default)]
+pub struct FftConfig {
+	// samples per fft, rounded up to a power of two
+	pub size:    usize,
+	// how much each fft overlaps the last one, 0.5 runs one every half a size of new samples
+	pub overlap: f32,
+	pub window:  Window,
+}
+
+impl Default for FftConfig {
+	fn default() -> Self {
+		Self {
+			size:    2048,
+			overlap: 0.5,
+			window:  Window::Hann,
+		}
+	}
+}
+
+// a named range of the spectrum whose energy is followed on its own
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(default)]
+pub struct Band {
+	pub name:     String,
+	pub low_hz:   f32,
+	pub high_hz:  f32,
+	// the energy in dB read as 0 and as 1
+	pub floor_db: f32,
+	pub ceil_db:  f32,
+}
+
+impl Default for Band {
+	fn default() -> Self {
+		Self {
+			name:     String::new(),
+			low_hz:   20.0,
+			high_hz:  20000.0,
+			floor_db: -90.0,
+			ceil_db:  -30.0,
+		}
+	}
+}
+
+impl Band {
+	pub fn defaults() -> Vec<Self> {
+		[("bass", 20.0, 250.0), ("mid", 250.0, 4000.0), ("high", 4000.0, 16000.0)].iter()
+			.map(|&(name, low_hz, high_hz)| Self { name: String::from(name), low_hz, high_hz, ..Default::default() })
+			.collect()
+	}
+}
+
+// how the left and right channels are made into the one signal everything is analysed from
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Downmix {
+	// left plus right
+	Sum,
+	Left,
+	Right,
+	// the average of both, what's in the middle of the stereo image
+	#[default]
+	Mid,
+	// half the difference, only what's panned or wide
+	Side,
+}
+
+impl Downmix {
+	fn apply(self, left: f32, right: f32) -> f32 {
+		match self {
+			Self::Sum   => left + right,
+			Self::Left  => left,
+			Self::Right => right,
+			Self::Mid   => (left + right) / 2.0,
+			Self::Side  => (left - right) / 2.0,
+		}
+	}
+}
+
 pub struct AudioProcessor {
+	// the last buffer_size samples, what the next fft runs over
 	pub buffer: Vec<f32>,
 	pub buffer_size: usize,
+	sample_rate: usize,
+	cfg: FftConfig,
+	window: Vec<f32>,
+	window_sum: f32,
+	// samples in since the last fft
+	pending: usize,
+	// dB of the bins from 0 Hz up to nyquist, following each fft by the envelope
+	smoothed: Vec<f32>,
+	attack_ms: f32,
+	release_ms: f32,
+	bands: Vec<Band>,
+	// 0..1 per band, following by the same envelope
+	energies: Vec<f32>,
+	// power weighted mean frequency in Hz, how bright the input sounds
+	centroid: f32,
+	// share of the power in each pitch class, C first
+	chroma: [f32; 12],
+	onset: crate::onset::Detector,
+	gain: crate::gain::Gain,
+	// onsets since take_onsets was last called
+	onsets: usize,
+	downmix: Downmix,
+	// a processor each for the left and right channel when they're analysed apart
+	sides: Vec<AudioProcessor>,
 	fft: Arc<dyn Fft<f32>>,
 }
 
 impl AudioProcessor {
-	pub fn new(sample_rate: usize, frame_rate: f32) -> Self {
-		let buffer_size = (sample_rate as f32 / frame_rate).ceil() as usize;
+	pub fn new(sample_rate: usize, cfg: FftConfig) -> Self {
+		let buffer_size = cfg.size.clamp(64, 1 << 16).next_power_of_two();
 		let mut planner: FftPlanner<f32> = FftPlanner::new();
 		let fft = planner.plan_fft_forward(buffer_size);
 
+		let window = cfg.window.coefficients(buffer_size);
+
 		Self {
 			buffer: vec![0.0; buffer_size],
 			buffer_size,
+			sample_rate,
+			window_sum: window.iter().sum(),
+			window,
+			pending: 0,
+			smoothed: vec![SILENCE_DB; buffer_size / 2 + 1],
+			attack_ms: 0.0,
+			release_ms: 0.0,
+			bands: Vec::new(),
+			energies: Vec::new(),
+			centroid: 0.0,
+			chroma: [0.0; 12],
+			onset: crate::onset::Detector::new(Default::default()),
+			onsets: 0,
+			gain: crate::gain::Gain::new(Default::default()),
+			downmix: Downmix::default(),
+			sides: Vec::new(),
+			cfg,
 			fft,
 		}
 	}
 
-	pub fn add_samples(&mut self, samples: &[f32]) {
-		self.buffer.extend_from_slice(samples);
-
-		// deal with possible race condition of the sketch
-		// update happening and requesting data before buffer is full.
-		// fft buffer may be too small before processing it
-		match self.buffer.len().cmp(&self.buffer_size) {
-			Ordering::Greater => {
-				self.buffer.drain(0..(self.buffer.len() - self.buffer_size));
-			},
-			Ordering::Less => {
-				while self.buffer.len() < self.buffer_size {
-					self.buffer.push(0.0);
-				}
-			},
-			_ => {},
+	// a new one with the same settings that hasn't heard anything yet
+	fn rebuilt(&self, sample_rate: usize) -> Self {
+		let mut ap = Self::new(sample_rate, self.cfg.clone());
+		ap.set_envelope(self.attack_ms, self.release_ms);
+		ap.set_bands(self.bands.clone());
+		ap.set_onset(self.onset.config().clone());
+		ap.set_gain(self.gain.config().clone());
+		ap.set_channels(self.downmix, !self.sides.is_empty());
+		ap
+	}
+
+	// stereo analyses left and right on their own as well as the downmix
+	pub fn set_channels(&mut self, downmix: Downmix, stereo: bool) {
+		self.downmix = downmix;
+		self.sides   = match stereo {
+			true  => (0..2).map(|_| {
+				let mut side = Self::new(self.sample_rate, self.cfg.clone());
+				side.set_envelope(self.attack_ms, self.release_ms);
+				side.set_bands(self.bands.clone());
+				side
+			}).collect(),
+			false => Vec::new(),
+		};
+	}
+
+	pub fn set_onset(&mut self, cfg: crate::onset::OnsetConfig) {
+		self.onset = crate::onset::Detector::new(cfg);
+	}
+
+	pub fn set_gain(&mut self, cfg: crate::gain::GainConfig) {
+		self.gain = crate::gain::Gain::new(cfg);
+	}
+
+	// the gate and agc stage, with the level of the input going into it
+	pub fn gain(&self) -> &crate::gain::Gain {
+		&self.gain
+	}
+
+	// how many onsets were heard since the last call
+	pub fn take_onsets(&mut self) -> usize {
+		std::mem::take(&mut self.onsets)
+	}
+
+	// spectral flux against the onset threshold, above 1 is an onset
+	pub fn onset_strength(&self) -> f32 {
+		self.onset.strength
+	}
+
+	// a new input came along, start over at its rate
+	pub fn set_sample_rate(&mut self, sample_rate: usize) {
+		if sample_rate != self.sample_rate {
+			*self = self.rebuilt(sample_rate);
+		}
+	}
+
+	// forget everything heard so far, back to silence
+	pub fn reset(&mut self) {
+		*self = self.rebuilt(self.sample_rate);
+	}
+
+	pub fn set_bands(&mut self, bands: Vec<Band>) {
+		self.sides.iter_mut().for_each(|side| side.set_bands(bands.clone()));
+		self.energies = vec![0.0; bands.len()];
+		self.bands    = bands;
+	}
+
+	pub fn bands(&self) -> &[Band] {
+		&self.bands
+	}
+
+	// how loud each band is between its floor and ceiling, in the order they were set
+	// followed by the left and then the right ones when analysing them apart
+	pub fn band_energies(&self) -> Vec<f32> {
+		let mut energies = self.energies.clone();
+		self.sides.iter().for_each(|side| energies.extend_from_slice(&side.energies));
+		energies
+	}
+
+	// new samples between two ffts
+	pub fn hop(&self) -> usize {
+		((self.buffer_size as f32 * (1.0 - self.cfg.overlap.clamp(0.0, 0.95))) as usize).max(1)
+	}
+
+	// takes interleaved left and right pairs, gated and levelled together then downmixed
+	pub fn add_samples(&mut self, frames: &[f32]) {
+		let mut frames = frames.to_vec();
+		self.gain.process(&mut frames, self.sample_rate * 2);
+
+		let mono = frames.chunks_exact(2).map(|f| self.downmix.apply(f[0], f[1])).collect::<Vec<f32>>();
+		self.add_mono(&mono);
+
+		self.sides.iter_mut().enumerate().for_each(|(channel, side)| {
+			side.add_mono(&frames.chunks_exact(2).map(|f| f[channel]).collect::<Vec<f32>>());
+		});
+	}
+
+	// runs an fft every hop worth of samples, the last one is what get_magnitudes gives back
+	fn add_mono(&mut self, samples: &[f32]) {
+		let hop = self.hop();
+
+		let mut rest = samples;
+		while !rest.is_empty() {
+			let take = (hop - self.pending).min(rest.len());
+			self.buffer.extend_from_slice(&rest[..take]);
+			self.pending += take;
+			rest = &rest[take..];
+
+			if self.pending == hop {
+				self.pending = 0;
+				self.trim();
+				self.analyse();
+			}
+		}
+
+		self.trim();
+	}
+
+	fn trim(&mut self) {
+		if self.buffer.len() > self.buffer_size {
+			self.buffer.drain(0..(self.buffer.len() - self.buffer_size));
 		}
 	}
 
-	pub fn get_magnitudes(&self, decay: f32) -> Vec<f32> {
-		let mut complex_input: Vec<Complex<f32>> =
-			self.buffer.iter().map(|&x| Complex::new(x, 0.0)).collect();
+	fn analyse(&mut self) {
+		let mut complex_input: Vec<Complex<f32>> = self.buffer.iter().zip(&self.window)
+			.map(|(&x, &w)| Complex::new(x * w, 0.0))
+			.collect();
 
 		self.fft.process(&mut complex_input);
 
-		// keep state on next call to this func
-		static mut DECAY_BUF: [f32; 1024] = [0.0; 1024];
+		// ms between two ffts, each bin eases towards the new level with a time constant
+		// of attack_ms going up and release_ms coming down, 0 ms follows right away
+		let dt = self.hop() as f32 / self.sample_rate as f32 * 1000.0;
+		let coef = |ms: f32| if ms > 0.0 { (-dt / ms).exp() } else { 0.0 };
+		let (attack, release) = (coef(self.attack_ms), coef(self.release_ms));
 
-		let mut mags = complex_input
-			.iter().map(|c| {
-				let mag = c.norm() / complex_input.len() as f32;
-				20.0 * (mag.max(1e-8)).log10()
-			})
+		let follow = |prev: &mut f32, next: f32| {
+			let coef = if next > *prev { attack } else { release };
+			*prev = next + (*prev - next) * coef;
+		};
+
+		// the top half mirrors the bottom one for real input
+		// scaled by the window so every window reads the same level
+		let mags = complex_input[..=self.buffer_size / 2].iter()
+			.map(|c| c.norm() / self.window_sum)
 			.collect::<Vec<f32>>();
 
-		// add decay
-		mags.iter_mut().zip(unsafe { DECAY_BUF.iter_mut() }).for_each(|(curr_frame, prev_frame)| {
-				// if the current sample is 'louder' than previous sample then apply the decay factor
-				if *curr_frame > *prev_frame {
-					*curr_frame = *prev_frame * decay;
-				}
+		mags.iter().zip(self.smoothed.iter_mut())
+			.for_each(|(mag, prev)| follow(prev, 20.0 * (mag.max(1e-8)).log10()));
+
+		let power = mags.iter().map(|m| m * m).sum::<f32>();
+		if power > 1e-12 {
+			let centroid = mags.iter().enumerate().map(|(bin, m)| self.frequency(bin) * m * m).sum::<f32>() / power;
+			follow(&mut self.centroid, centroid);
+
+			// fold each peak onto the pitch class of the nearest equal tempered note, A4 at 440 Hz
+			// low down the bins are wider than a semitone, so the peak's frequency is found between
+			// them from the parabola through its neighbours rather than taken from the bin
+			let mut chroma = [0.0; 12];
+			mags.windows(3).enumerate()
+				.filter(|(_, m)| m[1] > m[0] && m[1] >= m[2])
+				.map(|(bin, m)| {
+					let [a, b, c] = [m[0], m[1], m[2]].map(|m| m.max(1e-12).ln());
+					let curve = a - 2.0 * b + c;
+					let off   = if curve < 0.0 { 0.5 * (a - c) / curve } else { 0.0 };
+					((bin as f32 + 1.0 + off) * self.bin_hz(), m[1] * m[1])
+				})
+				.filter(|(hz, _)| (CHROMA_HZ.0..CHROMA_HZ.1).contains(hz))
+				.for_each(|(hz, power)| {
+					let note = (69.0 + 12.0 * (hz / 440.0).log2()).round() as i32;
+					chroma[note.rem_euclid(12) as usize] += power;
+				});
+
+			let total = chroma.iter().sum::<f32>();
+			if total > 1e-12 {
+				let coef = (-dt / CHROMA_MS).exp();
+				self.chroma.iter_mut().zip(chroma).for_each(|(prev, next)| *prev = next / total + (*prev - next / total) * coef);
+			}
+		}
+
+		if self.onset.process(&mags, dt) {
+			self.onsets += 1;
+		}
+
+		// summed power of the bins in each band, or the closest bin to a band narrower than one
+		let bin_hz = self.bin_hz();
+		self.bands.iter().zip(self.energies.iter_mut()).for_each(|(band, prev)| {
+			let last  = mags.len() - 1;
+			let first = ((band.low_hz / bin_hz).ceil() as usize).min(last);
+			let end   = ((band.high_hz / bin_hz).floor() as usize).clamp(first, last);
+
+			let power = mags[first..=end].iter().map(|m| m * m).sum::<f32>();
+			let db    = 10.0 * power.max(1e-16).log10();
+
+			let range = band.ceil_db - band.floor_db;
+			let level = if range > 0.0 { ((db - band.floor_db) / range).clamp(0.0, 1.0) } else { 0.0 };
+			follow(prev, level);
+		});
+	}
+
+	// spectral centroid in Hz, held where it was through silence
+	pub fn centroid(&self) -> f32 {
+		self.centroid
+	}
+
+	// share of the power in each pitch class, C first, held where it was through silence
+	pub fn chroma(&self) -> [f32; 12] {
+		self.chroma
+	}
+
+	// index into NOTES of the strongest pitch class, none until something's been heard
+	pub fn pitch_class(&self) -> Option<usize> {
+		self.chroma.iter().enumerate()
+			.filter(|(_, share)| **share > 0.0)
+			.max_by(|a, b| a.1.total_cmp(b.1))
+			.map(|(note, _)| note)
+	}
+
+	// frequency step between the bins get_magnitudes returns
+	pub fn bin_hz(&self) -> f32 {
+		self.sample_rate as f32 / self.buffer_size as f32
+	}
+
+	// centre frequency of a bin
+	pub fn frequency(&self, bin: usize) -> f32 {
+		bin as f32 * self.bin_hz()
+	}
+
+	// how fast the spectrum follows a bin getting louder and quieter, in milliseconds
+	pub fn set_envelope(&mut self, attack_ms: f32, release_ms: f32) {
+		self.sides.iter_mut().for_each(|side| side.set_envelope(attack_ms, release_ms));
+		self.attack_ms  = attack_ms.max(0.0);
+		self.release_ms = release_ms.max(0.0);
+	}
+
+	// the smoothed spectrum in dB, a bin per bin_hz from 0 Hz up
+	pub fn get_magnitudes(&self) -> Vec<f32> {
+		self.smoothed.clone()
+	}
+
+	// the left and right spectrum when analysing them apart, nothing otherwise
+	pub fn side_magnitudes(&self) -> Vec<Vec<f32>> {
+		self.sides.iter().map(Self::get_magnitudes).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const RATE: usize = 48000;
+
+	// left and right pairs of a sine of the same amplitude on both sides
+	fn sine(hz: f32, frames: usize, offset: usize) -> Vec<f32> {
+		(offset..offset + frames)
+			.map(|n| 0.5 * (std::f32::consts::TAU * hz * n as f32 / RATE as f32).sin())
+			.flat_map(|s| [s, s])
+			.collect()
+	}
+
+	fn loudest(mags: &[f32]) -> usize {
+		mags.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0
+	}
+
+	#[test]
+	fn a_sine_peaks_on_its_bin() {
+		let mut ap = AudioProcessor::new(RATE, FftConfig::default());
+		let hz = 50.0 * ap.bin_hz();
+		ap.add_samples(&sine(hz, 4096, 0));
+
+		assert_eq!(loudest(&ap.get_magnitudes()), (hz / ap.bin_hz()).round() as usize);
+	}
+
+	#[test]
+	fn an_fft_runs_every_hop() {
+		let mut ap  = AudioProcessor::new(RATE, FftConfig::default());
+		let hop     = ap.hop();
+		let hz      = 50.0 * ap.bin_hz();
+		assert_eq!(hop, 1024);
+
+		ap.add_samples(&sine(hz, hop - 1, 0));
+		assert!(ap.get_magnitudes().iter().all(|db| *db == SILENCE_DB));
+
+		ap.add_samples(&sine(hz, 1, hop - 1));
+		let first = ap.get_magnitudes();
+		assert!(first[50] > SILENCE_DB);
+
+		ap.add_samples(&sine(hz, hop - 1, hop));
+		assert_eq!(ap.get_magnitudes(), first);
+
+		ap.add_samples(&sine(hz, 1, 2 * hop - 1));
+		assert_ne!(ap.get_magnitudes(), first);
+	}
+
+	#[test]
+	fn the_envelope_eases_by_its_time_constants() {
+		let cfg = FftConfig { overlap: 0.0, ..Default::default() };
+		let (attack_ms, release_ms) = (100.0, 400.0);
+
+		let mut ap = AudioProcessor::new(RATE, cfg.clone());
+		ap.set_envelope(attack_ms, release_ms);
+		let hop = ap.hop();
+		let hz  = 50.0 * ap.bin_hz();
+		let dt  = hop as f32 / RATE as f32 * 1000.0;
+
+		// the level the sine reads at with nothing easing it
+		let mut instant = AudioProcessor::new(RATE, cfg);
+		instant.add_samples(&sine(hz, hop, 0));
+		let level = instant.get_magnitudes()[50];
 
-				// now set our static buffer frame to the current one for the next sample check
-				*prev_frame = *curr_frame;
-			});
+		ap.add_samples(&sine(hz, hop, 0));
+		let up = ap.get_magnitudes()[50];
+		let want = level + (SILENCE_DB - level) * (-dt / attack_ms).exp();
+		assert!((up - want).abs() < 1e-3, "{up} {want}");
 
-		mags
+		// a whole hop of silence reads as SILENCE_DB
+		ap.add_samples(&vec![0.0; hop * 2]);
+		let down = ap.get_magnitudes()[50];
+		let want = SILENCE_DB + (up - SILENCE_DB) * (-dt / release_ms).exp();
+		assert!((down - want).abs() < 1e-3, "{down} {want}");
 	}
 }
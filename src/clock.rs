@@ -0,0 +1,181 @@
+use std::time::{Duration, Instant};
+
+// midi clock sends 24 pulses per quarter note
+pub const PPQN: f32 = 24.0;
+
+// the tempo the visuals run at when nothing is driving them
+// so following an external clock scales time relative to this
+pub const REFERENCE_BPM: f32 = 120.0;
+
+// song position pointer counts in midi beats (16th notes) of 6 clocks each
+const CLOCKS_PER_SPP: u64 = 6;
+
+// no tick for this long means the external clock went away
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+// exponential smoothing of the tick interval to get rid of jitter
+// lower is smoother but takes longer to follow a tempo change
+const SMOOTHING: f32 = 0.05;
+
+#[derive(Debug, Default)]
+pub struct Clock {
+	// None until a start/stop/continue is received
+	// some gear (DJ mixers) only ever send ticks so we free run on those
+	running:     Option<bool>,
+	ticks:       u64,
+	intervals:   u32,
+	interval_ms: f32,
+	last_stamp:  Option<u32>,
+	last_tick:   Option<Instant>,
+	phase:       Option<f32>,
+}
+
+impl Clock {
+	// 0xF8 - timestamp is the portmidi timestamp in ms
+	pub fn tick(&mut self, stamp: u32) {
+		if let Some(last) = self.last_stamp {
+			let dt = stamp.wrapping_sub(last) as f32;
+
+			// a gap way longer than the current interval means the sender paused
+			// so don't let it drag the tempo estimate down
+			if self.intervals == 0 || dt < self.interval_ms * 4.0 {
+				self.intervals += 1;
+				// converge quickly on the first few ticks then settle into the smoothing
+				let alpha = SMOOTHING.max(1.0 / self.intervals as f32);
+				self.interval_ms += (dt - self.interval_ms) * alpha;
+			}
+		}
+
+		self.last_stamp = Some(stamp);
+		self.last_tick  = Some(Instant::now());
+
+		if self.running != Some(false) {
+			self.ticks += 1;
+		}
+	}
+
+	// 0xFA
+	pub fn start(&mut self) {
+		println!("[CLOCK]: start");
+		self.running = Some(true);
+		self.ticks   = 0;
+		self.phase   = Some(0.0);
+	}
+
+	// 0xFB
+	pub fn resume(&mut self) {
+		println!("[CLOCK]: continue at beat {:?}", self.beats());
+		self.running = Some(true);
+	}
+
+	// 0xFC
+	pub fn stop(&mut self) {
+		println!("[CLOCK]: stop at beat {:?}", self.beats());
+		self.running = Some(false);
+	}
+
+	// 0xF2 - 14 bit position split over the two data bytes
+	pub fn song_position(&mut self, lsb: u8, msb: u8) {
+		let spp = ((msb as u64 & 0x7F) << 7) | (lsb as u64 & 0x7F);
+		self.ticks = spp * CLOCKS_PER_SPP;
+		self.phase = Some(self.beats());
+		println!("[CLOCK]: song position {:?} beat {:?}", spp, self.beats());
+	}
+
+	pub fn is_synced(&self) -> bool {
+		self.intervals > 0 && self.last_tick.is_some_and(|t| t.elapsed() < TIMEOUT)
+	}
+
+	pub fn bpm(&self) -> Option<f32> {
+		(self.is_synced() && self.interval_ms > 0.0)
+			.then(|| 60000.0 / (self.interval_ms * PPQN))
+	}
+
+	// position in quarter notes since start or the last song position pointer
+	pub fn beats(&self) -> f32 {
+		self.ticks as f32 / PPQN
+	}
+
+	// how fast visual time should move compared to free running
+	pub fn rate(&self) -> f32 {
+		match self.bpm() {
+			// stays put after a stop whether or not the ticks keep coming
+			_ if self.running == Some(false) => 0.0,
+			Some(bpm) => bpm / REFERENCE_BPM,
+			None      => 1.0,
+		}
+	}
+
+	// a start or song position pointer asks the visuals to jump
+	// returns the visual time to jump to, which moves on by the frame time for every cell
+	pub fn take_phase(&mut self) -> Option<f32> {
+		let cells = (crate::renderer::GRID * crate::renderer::GRID) as f32;
+		self.phase.take().map(|beats| beats * 60.0 / REFERENCE_BPM * cells)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ticks_settle_on_the_tempo_through_jitter() {
+		let mut clock = Clock::default();
+
+		// 140 bpm is 60000 / (140 * 24) ms a tick, give or take a ms
+		let interval = 60000.0 / (140.0 * PPQN);
+		let mut stamp = 0.0;
+		for n in 0..PPQN as usize * 16 {
+			stamp += interval + if n % 2 == 0 { 1.0 } else { -1.0 };
+			clock.tick(stamp.round() as u32);
+		}
+
+		let bpm = clock.bpm().unwrap();
+		assert!((bpm - 140.0).abs() < 1.0, "{bpm}");
+		assert_eq!(clock.rate(), bpm / REFERENCE_BPM);
+	}
+
+	#[test]
+	fn a_long_gap_leaves_the_tempo_alone() {
+		let mut clock = Clock::default();
+		(0..48).for_each(|n| clock.tick(n * 20));
+		clock.tick(48 * 20 + 5000);
+
+		assert!((clock.interval_ms - 20.0).abs() < 0.01);
+	}
+
+	#[test]
+	fn stop_holds_after_the_ticks_go_away() {
+		let mut clock = Clock::default();
+		clock.start();
+		(0..48).for_each(|n| clock.tick(n * 20));
+		clock.stop();
+
+		clock.last_tick = Some(Instant::now() - TIMEOUT * 2);
+		assert!(!clock.is_synced());
+		assert_eq!(clock.rate(), 0.0);
+
+		clock.resume();
+		assert_eq!(clock.rate(), 1.0);
+	}
+
+	#[test]
+	fn song_position_jumps_to_the_beat() {
+		let mut clock = Clock::default();
+		clock.start();
+		assert_eq!(clock.take_phase(), Some(0.0));
+
+		// 32 midi beats of 16th notes is 8 quarter notes in
+		clock.song_position(32, 0);
+		assert_eq!(clock.beats(), 8.0);
+
+		// 8 beats at the reference tempo is 4 seconds of frames, each moving time on by a frame per cell
+		let cells = (crate::renderer::GRID * crate::renderer::GRID) as f32;
+		assert_eq!(clock.take_phase(), Some(4.0 * cells));
+		assert_eq!(clock.take_phase(), None);
+
+		// the 14 bits are split 7 and 7
+		clock.song_position(0, 1);
+		assert_eq!(clock.beats(), 128.0 * 6.0 / PPQN);
+	}
+}
@@ -0,0 +1,111 @@
+use crate::save_state::SaveState;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+	Trail,
+	Pixelate,
+	Posterise,
+	Threshold,
+	Invert,
+}
+
+// the order effects run in, each one does nothing at its default parameter
+// so the full chain is the default and presets can reorder or drop stages
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct EffectChain(pub Vec<Effect>);
+
+impl Default for EffectChain {
+	fn default() -> Self {
+		Self(vec![
+			Effect::Trail,
+			Effect::Pixelate,
+			Effect::Posterise,
+			Effect::Threshold,
+			Effect::Invert,
+		])
+	}
+}
+
+// run the save state's effect chain over a row major grid of rgb cells
+// prev holds the trail from the last frame and gets resized when the grid changes
+pub fn apply(ss: &SaveState, cells: &mut [[f32; 3]], width: usize, prev: &mut Vec<[f32; 3]>) {
+	if prev.len() != cells.len() {
+		*prev = vec![[0.0; 3]; cells.len()];
+	}
+
+	for effect in &ss.effects.0 {
+		match effect {
+			Effect::Trail     => trail(cells, prev, ss.trail),
+			Effect::Pixelate  => pixelate(cells, width, ss.pixelate.round() as usize),
+			Effect::Posterise => posterise(cells, ss.posterise.round()),
+			Effect::Threshold => threshold(cells, ss.threshold),
+			Effect::Invert    => if ss.invert { invert(cells) },
+		}
+	}
+}
+
+// keep the brighter of this frame and the fading last frame
+fn trail(cells: &mut [[f32; 3]], prev: &mut [[f32; 3]], amount: f32) {
+	let amount = amount.clamp(0.0, 0.999);
+
+	cells.iter_mut().zip(prev.iter_mut()).for_each(|(cell, prev)| {
+		cell.iter_mut().zip(prev.iter_mut()).for_each(|(c, p)| {
+			*c = c.max(*p * amount);
+			*p = *c;
+		});
+	});
+}
+
+// average blocks of cells together
+fn pixelate(cells: &mut [[f32; 3]], width: usize, block: usize) {
+	if block <= 1 || width == 0 { return; }
+
+	let height = cells.len() / width;
+
+	for by in (0..height).step_by(block) {
+		for bx in (0..width).step_by(block) {
+			let rows = by..(by + block).min(height);
+			let cols = bx..(bx + block).min(width);
+
+			let mut sum = [0.0; 3];
+			let mut n   = 0.0;
+			for y in rows.clone() {
+				for x in cols.clone() {
+					let cell = cells[y * width + x];
+					sum.iter_mut().zip(cell).for_each(|(s, c)| *s += c);
+					n += 1.0;
+				}
+			}
+
+			let avg = sum.map(|s| s / n);
+			for y in rows.clone() {
+				for x in cols.clone() {
+					cells[y * width + x] = avg;
+				}
+			}
+		}
+	}
+}
+
+// snap each channel to a number of levels
+fn posterise(cells: &mut [[f32; 3]], levels: f32) {
+	if levels < 2.0 { return; }
+
+	let steps = levels - 1.0;
+	cells.iter_mut().flatten().for_each(|c| *c = (*c * steps).round() / steps);
+}
+
+// black out cells darker than the threshold
+fn threshold(cells: &mut [[f32; 3]], level: f32) {
+	if level <= 0.0 { return; }
+
+	cells.iter_mut()
+		.filter(|[r, g, b]| 0.2126 * r + 0.7152 * g + 0.0722 * b < level)
+		.for_each(|cell| *cell = [0.0; 3]);
+}
+
+fn invert(cells: &mut [[f32; 3]]) {
+	cells.iter_mut().flatten().for_each(|c| *c = 1.0 - *c);
+}
@@ -0,0 +1,274 @@
+use crate::palette::Palette;
+use crate::save_state::SaveState;
+use crate::spectrum::Spectrum;
+use crate::transform::Transform;
+
+const TIME_OFFSET: f32 = 100000.0;
+
+pub type Rgb = [f32; 3];
+
+// anything that can colour a cell, a loaded plugin most of the time
+pub trait Patch: Sync {
+	fn time_divisor(&self) -> f32;
+	fn eval(&self, x: f32, y: f32, t: f32, bands: &[f32]) -> f32;
+}
+
+impl Patch for crate::loading::Plugin {
+	fn time_divisor(&self) -> f32 { self.time_divisor }
+
+	fn eval(&self, x: f32, y: f32, t: f32, bands: &[f32]) -> f32 { self.call(x, y, t, bands) }
+}
+
+// everything that decides what a frame looks like
+pub struct Input<'a> {
+	pub patch:      Option<&'a dyn Patch>,
+	pub save_state: &'a SaveState,
+	pub palette:    &'a Palette,
+	// turns added to every cell's colour, the key of the audio when following it
+	pub hue_shift:  f32,
+	pub mags:       &'a [f32],
+	// left and right spectrum when they're analysed apart, lighting the left and right half of the grid
+	pub sides:      &'a [Vec<f32>],
+	// frequency step between the fft bins of mags
+	pub bin_hz:     f32,
+	// 0..1 energy of the bands in audio.toml, in the order they're listed there
+	pub bands:      &'a [f32],
+	// visual time at the start of the frame and how far it moves on per cell
+	pub time:       f32,
+	pub step:       f32,
+	// turns the camera has spun so far
+	pub spin:       f32,
+	pub reset:      bool,
+	// size of the area the cell coordinates are spread over, centred on 0,0
+	pub size:       [f32; 2],
+}
+
+impl Input<'_> {
+	// visual time once every cell has moved it on
+	pub fn end_time(&self, cells: usize) -> f32 {
+		if self.reset { 0.0 } else { self.time + self.step * cells as f32 }
+	}
+}
+
+// a grid of colours, row major from the bottom left
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frame {
+	pub width:  usize,
+	pub height: usize,
+	pub cells:  Vec<Rgb>,
+}
+
+impl Frame {
+	pub fn new(width: usize, height: usize) -> Self {
+		Self { width, height, cells: vec![[0.0; 3]; width * height] }
+	}
+
+	// centre of a cell in the input's coordinates
+	pub fn cell_xy(&self, n: usize, size: [f32; 2]) -> (f32, f32) {
+		let (col, row) = (n % self.width, n / self.width);
+		(
+			(col as f32 + 0.5) * size[0] / self.width  as f32 - size[0] / 2.0,
+			(row as f32 + 0.5) * size[1] / self.height as f32 - size[1] / 2.0,
+		)
+	}
+
+	// rasterise into rgb bytes top row first, each cell stretched over its share of the image
+	pub fn to_rgb8(&self, width: u32, height: u32) -> Vec<u8> {
+		let mut out = Vec::with_capacity((width * height * 3) as usize);
+		if self.width == 0 || self.height == 0 {
+			out.resize((width * height * 3) as usize, 0);
+			return out;
+		}
+
+		for py in (0..height).rev() {
+			let row = (py as usize * self.height) / height as usize;
+			for px in 0..width {
+				let col = (px as usize * self.width) / width as usize;
+				out.extend(self.cells[row * self.width + col]
+					.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+			}
+		}
+
+		out
+	}
+
+	// colour every cell, split across the workers of the pool
+	pub fn evaluate(&mut self, input: &Input, pool: &mut scoped_threadpool::Pool) {
+		let ss      = input.save_state;
+		let camera  = Transform::new(ss, input.spin);
+		let modulo  = (ss.modulo_param + 1.0) as usize;
+		let width   = self.width;
+		let height  = self.height;
+		let bands   = ss.spectrum.bands(input.mags, input.bin_hz);
+		let sides   = input.sides.iter().map(|mags| ss.spectrum.bands(mags, input.bin_hz)).collect::<Vec<_>>();
+		let mut cells = std::mem::take(&mut self.cells);
+
+		let eval = |n: usize| -> Rgb {
+			// the order the window used to be subdivided in, time moved on a step per cell
+			// and the magnitudes were picked by it so keep both the same
+			let k = morton(n % width, n / width);
+
+			let (x, y) = self.cell_xy(n, input.size);
+
+			let cell_time = if input.reset { 0.0 } else { input.time + input.step * (k + 1) as f32 };
+
+			let hue = input.patch.map_or(0.0, |p| {
+				let t = cell_time / (p.time_divisor() + TIME_OFFSET * (ss.time_dialation / 10.0))
+					+ ss.current_intensity / 100.0;
+
+				let (x, y) = camera.apply(x, y);
+				p.eval(x, y, t, input.bands)
+			});
+
+			let (mags, bands) = match sides.len() {
+				2 => { let side = usize::from(n % width >= width / 2); (&input.sides[side][..], &sides[side][..]) },
+				_ => (input.mags, &bands[..]),
+			};
+
+			let mag = match ss.spectrum.position(n % width, n / width, width, height) {
+				Some(t) => Spectrum::sample(bands, t),
+				None    => (!mags.is_empty()).then(|| mags[(k % modulo) % mags.len()]),
+			};
+
+			let (lum, sat) = match mag {
+				Some(db) if ss.is_fft => ss.levels.map(db + ss.lum_mod),
+				_                     => (0.5, 1.0),
+			};
+
+			input.palette.color(hue + input.hue_shift, sat, lum)
+		};
+
+		let chunk = cells.len().div_ceil(pool.thread_count() as usize).max(1);
+
+		pool.scoped(|s| {
+			let eval = &eval;
+			cells.chunks_mut(chunk).enumerate().for_each(|(i, cells)| {
+				s.execute(move || cells.iter_mut().enumerate()
+					.for_each(|(j, cell)| *cell = eval(i * chunk + j)));
+			});
+		});
+
+		self.cells = cells;
+	}
+}
+
+// interleave the column and row bits, the index of the cell when the window
+// is subdivided into bottom left, bottom right, top left, top right recursively
+fn morton(col: usize, row: usize) -> usize {
+	(0..usize::BITS as usize / 2).fold(0, |k, bit| k
+		| ((col >> bit) & 1) << (2 * bit)
+		| ((row >> bit) & 1) << (2 * bit + 1))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// red on the left half, green on the right, whatever the time
+	struct Halves;
+
+	impl Patch for Halves {
+		fn time_divisor(&self) -> f32 { 1.0 }
+		fn eval(&self, x: f32, _y: f32, _t: f32, _bands: &[f32]) -> f32 { if x > 0.0 { 1.0 / 3.0 } else { 0.0 } }
+	}
+
+	// the hue is the time the cell was evaluated at
+	struct Clock;
+
+	impl Patch for Clock {
+		fn time_divisor(&self) -> f32 { 1.0 }
+		fn eval(&self, _x: f32, _y: f32, t: f32, _bands: &[f32]) -> f32 { t }
+	}
+
+	const RED:   Rgb = [1.0, 0.0, 0.0];
+	const GREEN: Rgb = [0.0, 1.0, 0.0];
+
+	fn input<'a>(patch: Option<&'a dyn Patch>, ss: &'a SaveState, palette: &'a Palette) -> Input<'a> {
+		Input {
+			patch,
+			save_state: ss,
+			palette,
+			hue_shift:  0.0,
+			mags:       &[],
+			sides:      &[],
+			bin_hz:     0.0,
+			bands:      &[],
+			time:       0.0,
+			step:       0.0,
+			spin:       0.0,
+			reset:      false,
+			size:       [2.0, 2.0],
+		}
+	}
+
+	fn hue() -> Palette {
+		Palette::builtins().remove(0)
+	}
+
+	#[test]
+	fn cells_take_the_patch_colour() {
+		let (ss, palette) = (SaveState::default(), hue());
+		let mut pool  = scoped_threadpool::Pool::new(2);
+		let mut frame = Frame::new(2, 2);
+
+		frame.evaluate(&input(Some(&Halves), &ss, &palette), &mut pool);
+		assert_eq!(frame.cells, [RED, GREEN, RED, GREEN]);
+	}
+
+	#[test]
+	fn no_patch_is_the_bottom_of_the_palette_turned_by_the_shift() {
+		let (ss, palette) = (SaveState::default(), hue());
+		let mut pool  = scoped_threadpool::Pool::new(1);
+		let mut frame = Frame::new(2, 2);
+
+		frame.evaluate(&input(None, &ss, &palette), &mut pool);
+		assert_eq!(frame.cells, [RED; 4]);
+
+		let shifted = Input { hue_shift: 1.0 / 3.0, ..input(None, &ss, &palette) };
+		frame.evaluate(&shifted, &mut pool);
+		assert_eq!(frame.cells, [GREEN; 4]);
+	}
+
+	#[test]
+	fn time_moves_on_a_step_per_cell_in_morton_order() {
+		let (ss, palette) = (SaveState::default(), hue());
+		let mut pool  = scoped_threadpool::Pool::new(3);
+		let mut frame = Frame::new(4, 4);
+
+		let input = Input { time: 0.5, step: 0.01, ..input(Some(&Clock), &ss, &palette) };
+		frame.evaluate(&input, &mut pool);
+
+		(0..16).for_each(|n| {
+			let k = morton(n % 4, n / 4);
+			assert_eq!(frame.cells[n], palette.color(0.5 + 0.01 * (k + 1) as f32, 1.0, 0.5), "cell {n}");
+		});
+		assert_eq!(input.end_time(frame.cells.len()), 0.5 + 0.01 * 16.0);
+
+		let reset = Input { reset: true, ..input };
+		frame.evaluate(&reset, &mut pool);
+		assert_eq!(frame.cells, [palette.color(0.0, 1.0, 0.5); 16]);
+		assert_eq!(reset.end_time(16), 0.0);
+	}
+
+	#[test]
+	fn rgb8_is_top_row_first_with_cells_stretched() {
+		let frame = Frame { width: 2, height: 2, cells: vec![RED, GREEN, [0.0, 0.0, 1.0], [2.0, -1.0, 0.5]] };
+
+		assert_eq!(frame.to_rgb8(4, 2), [
+			0, 0, 255,  0, 0, 255,  255, 0, 128,  255, 0, 128,
+			255, 0, 0,  255, 0, 0,  0, 255, 0,    0, 255, 0,
+		]);
+		assert_eq!(Frame::default().to_rgb8(1, 1), [0, 0, 0]);
+	}
+
+	#[test]
+	fn morton_walks_quadrants_bottom_left_first() {
+		let order = (0..16).map(|n| morton(n % 4, n / 4)).collect::<Vec<_>>();
+		assert_eq!(order, [
+			0,  1,  4,  5,
+			2,  3,  6,  7,
+			8,  9,  12, 13,
+			10, 11, 14, 15,
+		]);
+	}
+}
@@ -0,0 +1,139 @@
+// automatic gain and a noise gate, so a laptop mic and a line in from the mixer look alike
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(default)]
+pub struct GainConfig {
+	pub agc:         bool,
+	// rms level the agc turns the input up or down to
+	pub target_db:   f32,
+	// most the agc will turn a quiet input up by
+	pub max_gain_db: f32,
+	// how long the agc takes to settle on a new level
+	pub speed_ms:    f32,
+	// input quieter than this is silence, set it just above the noise floor
+	pub gate_db:     f32,
+}
+
+impl Default for GainConfig {
+	fn default() -> Self {
+		Self {
+			agc:         false,
+			target_db:   -20.0,
+			max_gain_db: 30.0,
+			speed_ms:    2000.0,
+			gate_db:     -120.0,
+		}
+	}
+}
+
+fn to_db(x: f32) -> f32 {
+	20.0 * x.max(1e-8).log10()
+}
+
+#[derive(Debug, Clone)]
+pub struct Gain {
+	cfg:         GainConfig,
+	pub gain_db: f32,
+	// the input as it comes in, before any gain, for telling if it's healthy
+	pub rms_db:  f32,
+	pub peak_db: f32,
+	pub is_open: bool,
+}
+
+impl Gain {
+	pub fn new(cfg: GainConfig) -> Self {
+		Self { cfg, gain_db: 0.0, rms_db: -160.0, peak_db: -160.0, is_open: false }
+	}
+
+	pub fn config(&self) -> &GainConfig {
+		&self.cfg
+	}
+
+	pub fn process(&mut self, samples: &mut [f32], sample_rate: usize) {
+		if samples.is_empty() { return; }
+
+		let dt_ms = samples.len() as f32 / sample_rate.max(1) as f32 * 1000.0;
+		let rms   = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+		let peak  = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+
+		self.rms_db = to_db(rms);
+		// peaks fall back at 20 dB a second so they can be read
+		self.peak_db = to_db(peak).max(self.peak_db - 0.02 * dt_ms);
+
+		self.is_open = self.rms_db >= self.cfg.gate_db;
+		if !self.is_open {
+			samples.fill(0.0);
+			return;
+		}
+
+		if self.cfg.agc {
+			let want = (self.cfg.target_db - self.rms_db).min(self.cfg.max_gain_db);
+			let coef = if self.cfg.speed_ms > 0.0 { (-dt_ms / self.cfg.speed_ms).exp() } else { 0.0 };
+			self.gain_db = want + (self.gain_db - want) * coef;
+
+			let gain = 10f32.powf(self.gain_db / 20.0);
+			samples.iter_mut().for_each(|s| *s *= gain);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const RATE: usize = 48000;
+
+	// a block of 10 ms at a steady rms
+	fn block(db: f32) -> Vec<f32> {
+		vec![10f32.powf(db / 20.0); RATE / 100]
+	}
+
+	#[test]
+	fn the_gate_silences_what_is_under_it() {
+		let mut gain = Gain::new(GainConfig { gate_db: -50.0, ..Default::default() });
+
+		let mut quiet = block(-60.0);
+		gain.process(&mut quiet, RATE);
+		assert!(!gain.is_open);
+		assert!(quiet.iter().all(|s| *s == 0.0));
+
+		let mut loud = block(-40.0);
+		gain.process(&mut loud, RATE);
+		assert!(gain.is_open);
+		assert_eq!(loud, block(-40.0));
+	}
+
+	#[test]
+	fn the_agc_settles_on_the_target() {
+		let mut gain = Gain::new(GainConfig { agc: true, target_db: -20.0, speed_ms: 100.0, ..Default::default() });
+
+		// 2 s is 20 time constants
+		let mut out = Vec::new();
+		(0..200).for_each(|_| {
+			out = block(-35.0);
+			gain.process(&mut out, RATE);
+		});
+
+		assert!((gain.gain_db - 15.0).abs() < 0.01, "{}", gain.gain_db);
+		assert!((to_db(out[0]) - -20.0).abs() < 0.01);
+		// the readout is of the input, before the gain
+		assert!((gain.rms_db - -35.0).abs() < 0.01);
+	}
+
+	#[test]
+	fn the_agc_stops_at_max_gain() {
+		let mut gain = Gain::new(GainConfig { agc: true, speed_ms: 0.0, max_gain_db: 30.0, ..Default::default() });
+		gain.process(&mut block(-80.0), RATE);
+		assert_eq!(gain.gain_db, 30.0);
+	}
+
+	#[test]
+	fn the_peak_falls_back_at_20_db_a_second() {
+		let mut gain = Gain::new(GainConfig::default());
+		gain.process(&mut block(0.0), RATE);
+		assert!(gain.peak_db.abs() < 0.01);
+
+		// half a second of quiet
+		(0..50).for_each(|_| gain.process(&mut block(-100.0), RATE));
+		assert!((gain.peak_db - -10.0).abs() < 0.01, "{}", gain.peak_db);
+	}
+}
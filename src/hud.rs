@@ -0,0 +1,69 @@
+use std::fmt::Write;
+
+use crate::audio_processor::AudioProcessor;
+use crate::mutstate::MutState;
+
+// what's going on, for the overlay since nobody reads stdout mid set
+pub fn text(ms: &MutState, fps: f32, ap: &AudioProcessor) -> String {
+	let mut out = String::new();
+
+	let plugin = ms.plugins.get(ms.save_state.active_func).map_or("none", |p| &p.name);
+	let _ = writeln!(out, "plugin    {} ({}/{})", plugin, ms.save_state.active_func + 1, ms.plugins.len());
+	let _ = writeln!(out, "palette   {}", ms.palette().map_or("none", |p| &p.name));
+	let _ = writeln!(out, "fps       {:.1}", fps);
+	let gain = ap.gain();
+	let _ = writeln!(out, "input     rms {:.1} dB peak {:.1} dB{}", gain.rms_db, gain.peak_db, match gain.peak_db {
+		db if db >= -0.1 => " CLIPPING",
+		_ if !gain.is_open => " gated",
+		_ => "",
+	});
+	if gain.config().agc {
+		let _ = writeln!(out, "agc       {:+.1} dB", gain.gain_db);
+	}
+
+	let _ = writeln!(out, "onsets    {} ({:.1}x threshold)", ms.onset_count, ap.onset_strength());
+
+	let _ = match ap.pitch_class() {
+		Some(note) => writeln!(out, "key       {} ({:.0}%)", crate::audio_processor::NOTES[note], ap.chroma()[note] * 100.0),
+		None       => writeln!(out, "key       -"),
+	};
+
+	ap.bands().iter().zip(ap.band_energies()).for_each(|(band, energy)| {
+		let _ = writeln!(out, "  {:<7} {:<10} {:.2}", band.name, "#".repeat((energy * 10.0).round() as usize), energy);
+	});
+
+	let _ = match ms.last_midi {
+		Some([status, data1, data2]) => writeln!(out, "midi      {:02X} {:3} {:3}", status, data1, data2),
+		None                         => writeln!(out, "midi      -"),
+	};
+
+	let _ = match ms.clock.bpm() {
+		Some(bpm) => writeln!(out, "clock     {:.1} bpm beat {:.1}", bpm, ms.clock.beats()),
+		None      => writeln!(out, "clock     free running"),
+	};
+
+	let flags = [
+		("fft",            ms.save_state.is_fft),
+		("backwards",      ms.is_backwards),
+		("reset",          ms.is_reset),
+		("listening_midi", ms.is_listening_midi),
+		("listening_keys", ms.is_listening_keys),
+		("saving_preset",  ms.is_saving_preset),
+		("quantise_held",  ms.is_quantise_held),
+	];
+	let on = flags.iter().filter(|(_, on)| *on).map(|(name, _)| *name).collect::<Vec<_>>();
+	let _ = writeln!(out, "flags     {}", on.join(" "));
+
+	if let Some((at, launch)) = &ms.pending_launch {
+		let _ = writeln!(out, "queued    {} at beat {:.0}", match launch {
+			crate::mutstate::Launch::Func(i)   => format!("func {}", i + 1),
+			crate::mutstate::Launch::Preset(p) => format!("preset {}", p.cc),
+		}, at);
+	}
+
+	// every save state value, whatever gets added to it later
+	out.push('\n');
+	out.push_str(&toml::to_string(&ms.save_state).unwrap_or_default());
+
+	out
+}
@@ -0,0 +1,70 @@
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+	#[default]
+	Linear,
+	// s curve, eases in and out
+	Smooth,
+	// lifts quiet parts
+	Log,
+	// only loud parts come through
+	Exp,
+}
+
+impl Curve {
+	fn apply(self, x: f32) -> f32 {
+		match self {
+			Self::Linear => x,
+			Self::Smooth => x * x * (3.0 - 2.0 * x),
+			Self::Log    => (1.0 + 9.0 * x).log10(),
+			Self::Exp    => (10f32.powf(x) - 1.0) / 9.0,
+		}
+	}
+}
+
+// how fft magnitudes turn into cell lightness and saturation
+// the defaults are what the lightness always used to be, saturation untouched
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Levels {
+	// dB range (after lum_mod is added) that gets spread over floor..ceil
+	pub db_floor:  f32,
+	pub db_ceil:   f32,
+	pub curve:     Curve,
+	pub gamma:     f32,
+	pub lum_floor: f32,
+	pub lum_ceil:  f32,
+	pub sat_floor: f32,
+	pub sat_ceil:  f32,
+}
+
+impl Default for Levels {
+	fn default() -> Self {
+		Self {
+			db_floor:  0.0,
+			db_ceil:   100.0,
+			curve:     Curve::Linear,
+			gamma:     1.0,
+			lum_floor: 0.01,
+			lum_ceil:  0.6,
+			sat_floor: 1.0,
+			sat_ceil:  1.0,
+		}
+	}
+}
+
+impl Levels {
+	// (lightness, saturation) for a magnitude in dB
+	pub fn map(&self, db: f32) -> (f32, f32) {
+		let range = self.db_ceil - self.db_floor;
+		let x = if range.abs() > f32::EPSILON { (db - self.db_floor) / range } else { 0.0 };
+		let x = if x.is_finite() { x.clamp(0.0, 1.0) } else { 0.0 };
+
+		let x = self.curve.apply(x).clamp(0.0, 1.0).powf(self.gamma.max(0.01));
+
+		(
+			self.lum_floor + (self.lum_ceil - self.lum_floor) * x,
+			self.sat_floor + (self.sat_ceil - self.sat_floor) * x,
+		)
+	}
+}
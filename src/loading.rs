@@ -1,8 +1,11 @@
 use libloading::{Symbol, Library};
 
+use std::sync::Arc;
+
 #[derive(Debug)]
 pub struct Plugin {
 	_lib: Library,
+	pub name:                 String,
 	pub time_divisor:         f32,
 	pub time_dialation_range: f32,
 	pub intensity_range:      f32,
@@ -11,17 +14,19 @@ pub struct Plugin {
 		x:           f32,                     // x 
 		y:           f32,                     // y 
 		t:           f32,                     // t 
-
-		// TODO: do something interesting with magnitudes in the function?
-		// fft:         *const std::ffi::c_void, // vec
-		// fft_len:     usize,                   // vec len
-		// fft_buf:     *const std::ffi::c_void, // buf
-		// fft_buf_len: usize                    // buf len
-	) -> f32
+	) -> f32,
+	// optional, called instead of transform when a plugin wants the audio bands
+	transform_bands: Option<unsafe extern "C" fn(
+		x:           f32,                     // x 
+		y:           f32,                     // y 
+		t:           f32,                     // t 
+		bands:       *const f32,              // band energies 0..1
+		bands_len:   usize                    // band count
+	) -> f32>,
 }
 
 impl Plugin {
-	pub fn load_dir(path: impl AsRef<std::path::Path>, plugs: &mut Vec<Self>) {
+	pub fn load_dir(path: impl AsRef<std::path::Path>, plugs: &mut Vec<Arc<Self>>) {
 		let mut files =  std::fs::read_dir(path).unwrap()
 			.filter_map(Result::ok)
 			.filter(|entry| entry.file_type().unwrap().is_file())
@@ -32,28 +37,32 @@ impl Plugin {
 
 		plugs.extend(
 			files.iter()
-				.map(|file| unsafe { Library::new(file).unwrap() })
-				.map(|lib| Self {
+				.map(|file| (file, unsafe { Library::new(file).unwrap() }))
+				.map(|(file, lib)| Self {
+					name:                  file.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned()),
 					transform:            *unsafe { lib.get(b"transform").unwrap() },
+					transform_bands:       unsafe { lib.get(b"transform_bands").ok().map(|s| *s) },
 					time_divisor:          unsafe { lib.get(b"TIME_DIVISOR").map_or(1000000000.0,  |s: Symbol<*const f32>| **s) },
 					time_dialation_range:  unsafe { lib.get(b"TIME_DIALATION_RANGE").map_or(100.0, |s: Symbol<*const f32>| **s) },
 					intensity_range:       unsafe { lib.get(b"INTENSITY_RANGE").map_or(0.01,       |s: Symbol<*const f32>| **s) },
 					lum_mod:               unsafe { lib.get(b"LUM_MOD").map_or(100.0,              |s: Symbol<*const f32>| **s) },
 					_lib: lib,
-				}));
+				})
+				.map(Arc::new));
+	}
+
+	// plugins are named after their file so "2_v2" can also be asked for as "v2"
+	pub fn is_named(&self, name: &str) -> bool {
+		self.name == name || self.name.split_once('_').is_some_and(|(_, n)| n == name)
 	}
 
 	pub fn call(&self, 
 		x: f32, y: f32, t: f32, 
-		// fft:     &[(f32, f32)], 
-		// fft_buf: &[f32; 69]
+		bands: &[f32],
 	) -> f32 {
-		unsafe { (self.transform)(
-			x, y, t, 
-			// fft.as_ptr() as *const std::ffi::c_void, 
-			// fft.len(),
-			// fft_buf.as_ptr() as *const std::ffi::c_void,
-			// fft_buf.len()
-		) }
+		match self.transform_bands {
+			Some(transform_bands) => unsafe { transform_bands(x, y, t, bands.as_ptr(), bands.len()) },
+			None                  => unsafe { (self.transform)(x, y, t) },
+		}
 	}
 }
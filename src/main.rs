@@ -1,9 +1,6 @@
-#![allow(static_mut_refs)]
-
 use portmidi::PortMidi;
 
 use nannou::prelude::*;
-use nannou_audio::Buffer;
 
 use std::path::Path;
 use std::sync::{Arc, LazyLock, Mutex};
@@ -14,19 +11,32 @@ use ringbuf::HeapRb;
 mod args;
 
 mod midi;
+mod clock;
 mod loading;
 mod audio_processor;
+mod audio_file;
+mod audio_device;
 mod utils;
 mod save_state;
 mod mutstate;
+mod palette;
+mod effects;
+mod transform;
+mod frame;
+mod renderer;
+mod offline;
+mod video;
+mod screenshot;
+mod hud;
+mod levels;
+mod spectrum;
+mod onset;
+mod gain;
+mod modulation;
 
 use save_state::SaveState;
 use mutstate::MutState;
 
-struct InputModel {
-	producer: ringbuf::HeapProd<f32>,
-}
-
 // struct OutputModel {
 // 	consumer: ringbuf::HeapCons<f32>,
 // }
@@ -35,6 +45,7 @@ struct State {
 	ms:              Arc<Mutex<MutState>>,
 	consumer:        ringbuf::HeapCons<f32>,
 	audio_processor: Arc<Mutex<audio_processor::AudioProcessor>>,
+	front:           Arc<Mutex<frame::Frame>>,
 }
 
 
@@ -47,13 +58,37 @@ static CONF_FILE:      LazyLock<&'static str> =
 		.map(|s| &*Box::leak(s.into_boxed_str()))
 		.unwrap_or("config.toml"));
 
+static AUDIO_CONF_FILE: LazyLock<String> =
+	LazyLock::new(|| std::env::var("AUDIO_CONF_FILE_PATH")
+		.unwrap_or(String::from("audio.toml")));
+
+static PALETTES_DIR: LazyLock<String> =
+	LazyLock::new(|| std::env::var("PALETTES_DIR")
+		.unwrap_or(String::from("palettes")));
+
 static PLUGIN_PATH: LazyLock<String> =
 	LazyLock::new(|| std::env::var("PLUGIN_PATH")
 		.unwrap_or(String::from("target/libs")));
 
+static SCREENSHOT_DIR: LazyLock<String> =
+	LazyLock::new(|| std::env::var("SCREENSHOT_DIR")
+		.unwrap_or(String::from("screenshots")));
+
+static RENDER_THREADS: LazyLock<usize> =
+	LazyLock::new(|| std::env::var("RENDER_THREADS").ok()
+		.and_then(|s| s.parse().ok())
+		.or(std::thread::available_parallelism().ok().map(|n| n.get()))
+		.unwrap_or(1)
+		.max(1));
+
 const SAMPLES: usize = 4096;
 
 fn main() {
+	if let Some(render) = &args::ARGS.render {
+		offline::render(render);
+		return;
+	}
+
 	let init = |a: &App| {
 		let pm_ctx = PortMidi::new().expect("could not get midi ctx");
 
@@ -66,16 +101,21 @@ fn main() {
 			},
 		};
 
+		let preset_map = SaveState::from_dir(&*PRESETS_DIR);
+		if let Some(midi) = &midi { midi.check_presets(&preset_map); }
+
 		let ms = Arc::new(Mutex::new(MutState {
-			preset_map: SaveState::from_dir(&*PRESETS_DIR),
+			preset_map,
 			save_state: SaveState::from_file(Path::new(&*PRESETS_DIR).join("default.toml"))
 				.unwrap_or_default(),
+			quantise: midi.as_ref().map_or(0, |m| m.cfg.quantise),
 			controller_name,
 			plugins: {
 				let mut p = Vec::new();
 				loading::Plugin::load_dir(&*PLUGIN_PATH, &mut p);
 				p
 			},
+			palettes: palette::Palette::load_dir(&*PALETTES_DIR),
 			..Default::default()
 		}));
 
@@ -102,36 +142,41 @@ fn main() {
 		}
 
 		// setup audio input
-		let audio_host = nannou_audio::Host::new();
-
-		let input_config = audio_host
-			.default_input_device().unwrap()
-			.default_input_config().unwrap();
-
-		println!("[MAIN]: default input {:#?}", input_config);
-
-		let audio_processor = Arc::new(Mutex::new(audio_processor::AudioProcessor::new(
-				input_config.sample_rate().0 as usize,
-				60.0)));
-
 		let ringbuffer = HeapRb::<f32>::new(SAMPLES * 2);
 
 		let (mut prod, cons) = ringbuffer.split();
 
 		(0..SAMPLES).for_each(|_| prod.try_push(0.0).unwrap());
 
-		std::thread::spawn(move || {
-			let in_model = InputModel { producer: prod };
-			let in_stream = audio_host
-				.new_input_stream(in_model)
-				.capture(pass_in)
-				.build()
-				.unwrap();
+		let audio_cfg = args::ARGS.audio.or(audio_device::AudioConfig::from_file(&*AUDIO_CONF_FILE));
 
-			loop {
-				in_stream.play().unwrap();
-			}
-		});
+		let audio_processor = Arc::new(Mutex::new(audio_processor::AudioProcessor::new(
+				audio_processor::DEFAULT_SAMPLE_RATE,
+				audio_cfg.fft.clone())));
+
+		audio_processor.lock().unwrap().set_bands(audio_cfg.bands.clone());
+		audio_processor.lock().unwrap().set_onset(audio_cfg.onset.clone());
+		audio_processor.lock().unwrap().set_gain(audio_cfg.gain.clone());
+		audio_processor.lock().unwrap().set_channels(audio_cfg.downmix, audio_cfg.stereo);
+
+		{
+			let mut ms = ms.lock().unwrap();
+			ms.onset_actions = audio_cfg.onset.actions.clone();
+			ms.onset_every   = audio_cfg.onset.every;
+		}
+
+		match &args::ARGS.audio_file {
+			Some(path) => {
+				let file = audio_file::AudioFile::open(path).unwrap_or_else(|e| {
+					eprintln!("[MAIN]: Error reading audio file {:?}: {e}", path);
+					std::process::exit(1);
+				});
+
+				audio_processor.lock().unwrap().set_sample_rate(file.sample_rate);
+				std::thread::spawn(move || file.play(prod));
+			},
+			None => audio_device::spawn(audio_cfg, prod, audio_processor.clone()),
+		}
 
 		if args::ARGS.hmr_enable {
 			let plugs_count = std::fs::read_dir(&*PLUGIN_PATH).map_or(0, 
@@ -151,6 +196,7 @@ fn main() {
 			.build().unwrap();
 
 		State {
+			front: renderer::spawn(ms.clone(), audio_processor.clone()),
 			ms,
 			consumer: cons,
 			audio_processor,
@@ -162,12 +208,6 @@ fn main() {
 		.run();
 }
 
-fn pass_in(model: &mut InputModel, buffer: &Buffer) {
-	buffer.frames().for_each(|f| 
-		f.iter().for_each(|s| { 
-			let _ = model.producer.try_push(*s); }));
-}
-
 fn key_released(_: &App, s: &mut State, key: Key) {
 	let mut ms = s.ms.lock().unwrap();
 	match key {
@@ -193,6 +233,11 @@ fn key_pressed(_: &App, s: &mut State, key: Key) {
 			println!("[MAIN][KEYS]: is_listening_keys true");
 			ms.is_listening_keys = true;
 		}
+		Key::C => ms.step_palette(1),
+		Key::X => ms.step_palette(-1),
+		Key::I => ms.save_state.invert = !ms.save_state.invert,
+		Key::S => ms.is_taking_screenshot = true,
+		Key::H => ms.is_showing_hud = !ms.is_showing_hud,
 
 		Key::Key1 => ms.set_active_func(0),
 		Key::Key2 => ms.set_active_func(1),
@@ -227,19 +272,24 @@ fn key_pressed(_: &App, s: &mut State, key: Key) {
 	}
 }
 
-fn update(_app: &App, state: &mut State,_update: Update) {
+fn update(app: &App, state: &mut State,_update: Update) {
 	let mut ms = state.ms.lock().unwrap();
 
-	if ms.save_state.is_fft {
-		let mut buffer = [0.0; 1024];
+	let window = app.window_rect();
+	ms.window = [window.w(), window.h()];
 
-		state.consumer.pop_slice(&mut buffer);
+	if ms.save_state.is_fft {
+		let buffer = state.consumer.pop_iter().collect::<Vec<f32>>();
 
 		let mut ap = state.audio_processor.lock().unwrap();
 
 		ap.add_samples(&buffer);
+
+		(0..ap.take_onsets()).for_each(|_| ms.onset());
 	}
 
+	ms.update_launch();
+
 	ms.save_preset().unwrap_or_else(|e| {
 		eprintln!("[MAIN]: Error saving preset: {e}");
 	});
@@ -249,69 +299,36 @@ fn update(_app: &App, state: &mut State,_update: Update) {
 fn view(app: &App, s: &State, frame: Frame) {
 	let draw = app.draw();
 	draw.background().color(BLACK);
-	let mut ms = s.ms.lock().unwrap();
-	let ap = s.audio_processor.lock().unwrap();
-
-	let mags = ap.get_magnitudes(ms.save_state.decay_param);
-	
-	static mut TIME: f32 = 0.0;
-
-	const UPPER_TIME_LIMIT: f32 = 524288.0;
-	const LOWER_TIME_LIMIT: f32 = -524288.0;
-	if unsafe { TIME >= UPPER_TIME_LIMIT || TIME <= LOWER_TIME_LIMIT } {
-		ms.is_backwards = !ms.is_backwards;
-	}
-	
-	let mut i: i32 = -1;
-	for r in app.window_rect().subdivisions_iter()
-		.flat_map(|r| r.subdivisions_iter())
-		.flat_map(|r| r.subdivisions_iter())
-		.flat_map(|r| r.subdivisions_iter())
-		.flat_map(|r| r.subdivisions_iter())
-		.flat_map(|r| r.subdivisions_iter())
-	{
-		i += 1;
-
-		// TODO: figure out how to get mags.len() to 4096!
-		// if i == mags.len() {
-
-		if i % (ms.save_state.modulo_param + 1.0) as i32 == 0 {
-			i = 0;
-		}
 
-		match ms.is_backwards {
-			true => unsafe { TIME -= app.duration.since_prev_update.as_secs_f32() },
-			_    => unsafe { TIME += app.duration.since_prev_update.as_secs_f32() },
-		}
-
-		const THRESHOLD: f32 = 1000000000.0;
-		if unsafe { TIME >= THRESHOLD || TIME <= -THRESHOLD } {
-			ms.is_backwards = !ms.is_backwards;
-		}
-
-		if ms.is_reset { unsafe { TIME = 0.0; } }
-
-
-		const TIME_OFFSET: f32 = 100000.0;
+	// the renderer thread already worked the colours out, just draw them
+	let front   = s.front.lock().unwrap();
+	let window  = app.window_rect();
+	let cell_wh = window.wh() / vec2(front.width as f32, front.height as f32);
 
-		let mut hue: f32 = 0.0;
-		if !ms.plugins.is_empty() {
-			let t: f32 = unsafe { TIME } / (
-					ms.plugins[ms.save_state.active_func].time_divisor
-					+ TIME_OFFSET
-					* (ms.save_state.time_dialation / 10.0)
-				)
-				+ ms.save_state.current_intensity / 100.0;
-
-			hue = ms.plugins[ms.save_state.active_func].call(r.x(), r.y(), t);
-		}
-
-		let lum = if ms.save_state.is_fft {
-			utils::lerp_float((mags[i as usize] + ms.save_state.lum_mod).ceil() as u8, 0.01, 0.6, 0, 100)
-		} else { 0.5 };
-
-		draw.rect().xy(r.xy()).wh(r.wh())
-			.hsl(hue, 1.0, lum);
+	front.cells.iter().enumerate().for_each(|(n, [red, green, blue])| {
+		let (x, y) = front.cell_xy(n, [window.w(), window.h()]);
+		let xy = window.xy() + vec2(x, y);
+		draw.rect().xy(xy).wh(cell_wh)
+			.rgb(*red, *green, *blue);
+	});
+	drop(front);
+
+	let ms = s.ms.lock().unwrap();
+	if ms.is_showing_hud {
+		let text = hud::text(&ms, app.fps(), &s.audio_processor.lock().unwrap());
+		drop(ms);
+
+		const HUD_MARGIN: f32 = 10.0;
+		let hud_wh = vec2(360.0, 560.0);
+		let xy     = window.top_left() + vec2(hud_wh.x / 2.0 + HUD_MARGIN, -hud_wh.y / 2.0 - HUD_MARGIN);
+
+		draw.rect().xy(xy).wh(hud_wh + HUD_MARGIN * 2.0)
+			.rgba(0.0, 0.0, 0.0, 0.6);
+		draw.text(&text).xy(xy).wh(hud_wh)
+			.left_justify()
+			.align_text_top()
+			.font_size(12)
+			.color(WHITE);
 	}
 
 	draw.to_frame(app, &frame).unwrap();
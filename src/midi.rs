@@ -1,6 +1,12 @@
 use portmidi::{DeviceInfo, MidiEvent};
 use std::collections::HashMap;
 
+use crate::mutstate::Launch;
+
+// the full throw of the decay_factor and decay_param controls
+pub const ATTACK_MAX_MS:  f32 = 500.0;
+pub const RELEASE_MAX_MS: f32 = 2000.0;
+
 #[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
 pub struct DeviceConfig {
 	pub backwards:         u8,
@@ -14,8 +20,59 @@ pub struct DeviceConfig {
 	pub decay_param:       u8,
 	pub is_listening_midi: u8,
 	pub is_saving_preset:  u8,
+	#[serde(default)]
+	pub palette_next:      u8,
+	#[serde(default)]
+	pub trail:             u8,
+	#[serde(default)]
+	pub pixelate:          u8,
+	#[serde(default)]
+	pub posterise:         u8,
+	#[serde(default)]
+	pub threshold:         u8,
+	#[serde(default)]
+	pub invert:            u8,
+	#[serde(default)]
+	pub zoom:              u8,
+	#[serde(default)]
+	pub rotation:          u8,
+	#[serde(default)]
+	pub spin:              u8,
+	#[serde(default)]
+	pub pan_x:             u8,
+	#[serde(default)]
+	pub pan_y:             u8,
+	#[serde(default)]
+	pub mirror:            u8,
+	#[serde(default)]
+	pub kaleido:           u8,
+	#[serde(default)]
+	pub screenshot:        u8,
+	#[serde(default)]
+	pub hud:               u8,
 	pub fns:               Box<[u8]>,
 	pub name:              String,
+
+	// beats to wait for before launching a function or preset, 0 launches right away
+	#[serde(default)]
+	pub quantise:          u8,
+	// held down this launches right away regardless of quantise
+	#[serde(default)]
+	pub quantise_hold:     u8,
+}
+
+impl DeviceConfig {
+	// the control bound to a cc, any message on it goes there before the preset and cc learn buttons
+	pub fn control_on(&self, cc: u8) -> Option<String> {
+		if cc == 0 { return None; }
+
+		let toml::Value::Table(fields) = toml::Value::try_from(self).ok()? else { return None; };
+		fields.into_iter()
+			// a number of beats, not a cc
+			.filter(|(name, _)| name != "quantise")
+			.find(|(_, value)| value.as_integer() == Some(cc as i64))
+			.map(|(name, _)| name)
+	}
 }
 
 #[derive(Debug)]
@@ -54,8 +111,27 @@ impl Midi {
 
 	}
 
+	// presets saved on a button that's since been bound to a control can't be recalled any more
+	pub fn check_presets(&self, preset_map: &[(String, Vec<crate::save_state::SaveState>)]) {
+		preset_map.iter()
+			.filter(|(controller, _)| controller == &self.cfg.name)
+			.flat_map(|(_, presets)| presets)
+			.filter_map(|preset| Some((preset.cc, self.cfg.control_on(preset.cc)?)))
+			.for_each(|(cc, control)| eprintln!(
+				"[MIDI][WARN]: the preset on cc {:?} can't be recalled, that button is bound to {:?} now", cc, control));
+	}
+
 	// TODO: setup a debugger?? :o
 	pub fn handle_msg(&self, me: MidiEvent, ms: &mut crate::MutState) {
+		// system realtime and song position come from whatever is sending clock
+		// and carry no controller data so don't let them reach the controller handlers
+		if me.message.status >= 0xF0 {
+			Self::handle_system_msg(me, ms);
+			return;
+		}
+
+		ms.last_midi = Some([me.message.status, me.message.data1, me.message.data2]);
+
 		match self.cfg.name.as_str() {
 			"XONE:K2 " | "XONE:K2" => self.handle_xonek2_msg(me, ms),
 			"WINE ALSA Output #1"  => self.handle_ableton_msg(me, ms),
@@ -66,6 +142,17 @@ impl Midi {
 		}
 	}
 
+	fn handle_system_msg(me: MidiEvent, ms: &mut crate::MutState) {
+		match me.message.status {
+			0xF8 => ms.clock.tick(me.timestamp),
+			0xFA => ms.clock.start(),
+			0xFB => ms.clock.resume(),
+			0xFC => ms.clock.stop(),
+			0xF2 => ms.clock.song_position(me.message.data1, me.message.data2),
+			_ => (),
+		}
+	}
+
 	fn handle_ableton_msg(&self, me: MidiEvent, ms: &mut crate::MutState) {
 		let channel   = me.message.data1;
 
@@ -88,7 +175,7 @@ impl Midi {
 
 	fn set_active_func(&self, channel: u8, ms: &mut crate::MutState) {
 		if let Some(i) = self.cfg.fns.iter().position(|f| *f == channel) {
-			ms.save_state.active_func = i;
+			ms.launch(Launch::Func(i));
 		}
 	}
 
@@ -105,10 +192,10 @@ impl Midi {
 				ms.save_state.time_dialation    = lerp_with_range(ms.plugins[ms.save_state.active_func].time_dialation_range);
 				ms.save_state.current_intensity = lerp_with_range(ms.plugins[ms.save_state.active_func].intensity_range);
 			},
-			// c if c == self.cfg.decay_factor     => ms.save_state.decay_factor      = lerp_with_range(1.0),
+			// c if c == self.cfg.decay_factor     => ms.save_state.decay_factor      = lerp_with_range(ATTACK_MAX_MS),
 			// c if c == self.cfg.lum_mod          => ms.save_state.lum_mod           = lerp_with_range(ms.plugins[ms.save_state.active_func].lum_mod),
 			// c if c == self.cfg.modulo_param     => ms.save_state.modulo_param      = lerp_with_range(368.0),
-			// c if c == self.cfg.decay_param      => ms.save_state.decay_param       = lerp_with_range(0.9999),
+			// c if c == self.cfg.decay_param      => ms.save_state.decay_param       = lerp_with_range(RELEASE_MAX_MS),
 
 			_ => {
 				// not mapped by the config or the well-known cc list
@@ -125,9 +212,17 @@ impl Midi {
 		let lerp_with_range = |range| crate::utils::lerp_float(intensity, 0.0, range, 0, 127);
 
 		match channel {
+			// held modifier
+			c if c == self.cfg.quantise_hold    => ms.is_quantise_held  = intensity > 0,
+
 			// latched boolean when condition matches
 			c if c == self.cfg.backwards        && intensity == 127 => ms.is_backwards      = !ms.is_backwards,
 			c if c == self.cfg.is_fft           && intensity == 127 => ms.save_state.is_fft = !ms.save_state.is_fft,
+			c if c == self.cfg.palette_next     && intensity == 127 => ms.step_palette(1),
+			c if c == self.cfg.invert           && intensity == 127 => ms.save_state.invert = !ms.save_state.invert,
+			c if c == self.cfg.mirror           && intensity == 127 => ms.save_state.mirror = ms.save_state.mirror.next(),
+			c if c == self.cfg.screenshot       && intensity == 127 => ms.is_taking_screenshot = true,
+			c if c == self.cfg.hud              && intensity == 127 => ms.is_showing_hud = !ms.is_showing_hud,
 
 			c if c == self.cfg.is_listening_midi && intensity == 127 => {
 				println!("[MIDI]: is_listening_midi - true");
@@ -142,11 +237,21 @@ impl Midi {
 
 			// continuous control values
 			c if c == self.cfg.intensity        => ms.save_state.current_intensity = lerp_with_range(ms.plugins[ms.save_state.active_func].intensity_range),
-			c if c == self.cfg.decay_factor     => ms.save_state.decay_factor      = lerp_with_range(1.0),
+			c if c == self.cfg.decay_factor     => ms.save_state.decay_factor      = lerp_with_range(ATTACK_MAX_MS),
 			c if c == self.cfg.time_dialation   => ms.save_state.time_dialation    = lerp_with_range(ms.plugins[ms.save_state.active_func].time_dialation_range),
 			c if c == self.cfg.lum_mod          => ms.save_state.lum_mod           = lerp_with_range(ms.plugins[ms.save_state.active_func].lum_mod),
 			c if c == self.cfg.modulo_param     => ms.save_state.modulo_param      = lerp_with_range(368.0),
-			c if c == self.cfg.decay_param      => ms.save_state.decay_param       = lerp_with_range(0.9999),
+			c if c == self.cfg.decay_param      => ms.save_state.decay_param       = lerp_with_range(RELEASE_MAX_MS),
+			c if c == self.cfg.trail            => ms.save_state.trail             = lerp_with_range(0.98),
+			c if c == self.cfg.pixelate         => ms.save_state.pixelate          = lerp_with_range(16.0),
+			c if c == self.cfg.posterise        => ms.save_state.posterise         = lerp_with_range(16.0),
+			c if c == self.cfg.threshold        => ms.save_state.threshold         = lerp_with_range(1.0),
+			c if c == self.cfg.zoom             => ms.save_state.zoom              = crate::utils::lerp_float(intensity, -4.0, 4.0, 0, 127),
+			c if c == self.cfg.rotation         => ms.save_state.rotation          = lerp_with_range(1.0),
+			c if c == self.cfg.spin             => ms.save_state.spin              = crate::utils::lerp_float(intensity, -0.5, 0.5, 0, 127),
+			c if c == self.cfg.pan_x            => ms.save_state.pan_x             = crate::utils::lerp_float(intensity, -500.0, 500.0, 0, 127),
+			c if c == self.cfg.pan_y            => ms.save_state.pan_y             = crate::utils::lerp_float(intensity, -500.0, 500.0, 0, 127),
+			c if c == self.cfg.kaleido          => ms.save_state.kaleido           = lerp_with_range(12.0),
 
 			// do nothing on zero for now...
 			// because intensity is being used for division
@@ -177,9 +282,12 @@ impl Midi {
 				ms.save_state.cc = channel;
 			},
 			_ if intensity == 127 => {
-				if let Some(preset) = ms.preset_map.iter().find(|(c, _)| c == &ms.controller_name)
-					.and_then(|(_, presets)| presets.iter().find(|p| p.cc == channel)) {
-					ms.save_state = preset.clone();
+				let preset = ms.preset_map.iter().find(|(c, _)| c == &ms.controller_name)
+					.and_then(|(_, presets)| presets.iter().find(|p| p.cc == channel))
+					.cloned();
+
+				if let Some(preset) = preset {
+					ms.launch(Launch::Preset(preset));
 				}
 			},
 
@@ -193,3 +301,19 @@ impl Midi {
 		ms.is_saving_preset = channel == self.cfg.is_saving_preset && intensity > 0;
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn controls_are_found_by_cc_but_quantise_is_beats() {
+		let cfg = DeviceConfig { intensity: 16, quantise: 16, quantise_hold: 39, ..Default::default() };
+
+		assert_eq!(cfg.control_on(39).as_deref(), Some("quantise_hold"));
+		assert_eq!(cfg.control_on(16).as_deref(), Some("intensity"));
+		assert_eq!(cfg.control_on(40), None);
+		// unbound controls are all 0
+		assert_eq!(cfg.control_on(0), None);
+	}
+}
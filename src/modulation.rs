@@ -0,0 +1,181 @@
+use crate::audio_processor::AudioProcessor;
+use crate::save_state::SaveState;
+
+// an audio feature, read as 0..1
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+	// energy of the `[[bands]]` entry in audio.toml with this name
+	Band(String),
+	// input level, -60 dB to 0 dB
+	Rms,
+	// spectral flux against the onset threshold, reaching 1 at the threshold
+	Onset,
+	// spectral centroid, 20 Hz to 20 kHz on a log scale
+	Centroid,
+}
+
+// the save state parameter a route moves
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+	CurrentIntensity,
+	TimeDialation,
+	LumMod,
+	ModuloParam,
+	Trail,
+	Pixelate,
+	Posterise,
+	Threshold,
+	Zoom,
+	Rotation,
+	Spin,
+	PanX,
+	PanY,
+	Kaleido,
+}
+
+impl Target {
+	fn param(self, ss: &mut SaveState) -> &mut f32 {
+		match self {
+			Self::CurrentIntensity => &mut ss.current_intensity,
+			Self::TimeDialation    => &mut ss.time_dialation,
+			Self::LumMod           => &mut ss.lum_mod,
+			Self::ModuloParam      => &mut ss.modulo_param,
+			Self::Trail            => &mut ss.trail,
+			Self::Pixelate         => &mut ss.pixelate,
+			Self::Posterise        => &mut ss.posterise,
+			Self::Threshold        => &mut ss.threshold,
+			Self::Zoom             => &mut ss.zoom,
+			Self::Rotation         => &mut ss.rotation,
+			Self::Spin             => &mut ss.spin,
+			Self::PanX             => &mut ss.pan_x,
+			Self::PanY             => &mut ss.pan_y,
+			Self::Kaleido          => &mut ss.kaleido,
+		}
+	}
+
+	// the same ranges the controller sets them in, so a deep route can't break a frame
+	fn clamp(self, value: f32, ranges: Ranges) -> f32 {
+		match self {
+			Self::CurrentIntensity => value.clamp(0.0, ranges.intensity.max(0.0)),
+			Self::TimeDialation    => value.clamp(0.0, ranges.time_dialation.max(0.0)),
+			Self::LumMod           => value.clamp(0.0, ranges.lum_mod.max(0.0)),
+			Self::ModuloParam      => value.clamp(0.0, 368.0),
+			Self::Trail            => value.clamp(0.0, 0.98),
+			Self::Pixelate | Self::Posterise => value.clamp(0.0, 16.0),
+			Self::Threshold | Self::Rotation => value.clamp(0.0, 1.0),
+			Self::Zoom             => value.clamp(-4.0, 4.0),
+			Self::Spin             => value.clamp(-0.5, 0.5),
+			Self::PanX | Self::PanY => value.clamp(-500.0, 500.0),
+			Self::Kaleido          => value.clamp(0.0, 12.0),
+		}
+	}
+}
+
+// the tops of the controls that go as far as the active plugin says
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ranges {
+	pub intensity:      f32,
+	pub time_dialation: f32,
+	pub lum_mod:        f32,
+}
+
+impl From<&crate::loading::Plugin> for Ranges {
+	fn from(plugin: &crate::loading::Plugin) -> Self {
+		Self {
+			intensity:      plugin.intensity_range,
+			time_dialation: plugin.time_dialation_range,
+			lum_mod:        plugin.lum_mod,
+		}
+	}
+}
+
+// adds offset + depth * source to the target on top of what the preset or controller set it to
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Route {
+	pub source:       Source,
+	pub target:       Target,
+	#[serde(default)]
+	pub depth:        f32,
+	#[serde(default)]
+	pub offset:       f32,
+	// time constant the source is eased by, 0 follows right away
+	#[serde(default)]
+	pub smoothing_ms: f32,
+}
+
+// the smoothed source of each route, carried from frame to frame
+#[derive(Debug, Default)]
+pub struct Modulator {
+	values: Vec<f32>,
+}
+
+impl Modulator {
+	// moves the parameters of ss, a copy for this frame, by each of its routes
+	pub fn apply(&mut self, ss: &mut SaveState, ap: &AudioProcessor, dt: f32, ranges: Ranges) {
+		if self.values.len() != ss.routes.len() {
+			self.values = vec![0.0; ss.routes.len()];
+		}
+
+		let routes = std::mem::take(&mut ss.routes);
+		let dt_ms  = dt * 1000.0;
+		for (route, value) in routes.iter().zip(self.values.iter_mut()) {
+			let next = read(&route.source, ap);
+			let coef = if route.smoothing_ms > 0.0 { (-dt_ms / route.smoothing_ms).exp() } else { 0.0 };
+			*value = next + (*value - next) * coef;
+
+			let param = route.target.param(ss);
+			*param = route.target.clamp(*param + route.offset + route.depth * *value, ranges);
+		}
+		ss.routes = routes;
+	}
+}
+
+fn read(source: &Source, ap: &AudioProcessor) -> f32 {
+	match source {
+		Source::Band(name) => ap.bands().iter().zip(ap.band_energies())
+			.find(|(band, _)| &band.name == name)
+			.map_or(0.0, |(_, energy)| energy),
+		Source::Rms      => ((ap.gain().rms_db + 60.0) / 60.0).clamp(0.0, 1.0),
+		Source::Onset    => ap.onset_strength().clamp(0.0, 1.0),
+		Source::Centroid => ((ap.centroid().max(20.0) / 20.0).log2() / 1000f32.log2()).clamp(0.0, 1.0),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn targets_stay_in_the_controller_range() {
+		use Target::*;
+
+		let plugin = Ranges { intensity: 0.01, time_dialation: 5.0, lum_mod: 80.0 };
+
+		// the match won't build without a range for a new target, add it to the list too
+		let range = |target: Target| match target {
+			CurrentIntensity      => (0.0, 0.01),
+			TimeDialation         => (0.0, 5.0),
+			LumMod                => (0.0, 80.0),
+			ModuloParam           => (0.0, 368.0),
+			Trail                 => (0.0, 0.98),
+			Pixelate | Posterise  => (0.0, 16.0),
+			Threshold | Rotation  => (0.0, 1.0),
+			Zoom                  => (-4.0, 4.0),
+			Spin                  => (-0.5, 0.5),
+			PanX | PanY           => (-500.0, 500.0),
+			Kaleido               => (0.0, 12.0),
+		};
+
+		[
+			CurrentIntensity, TimeDialation, LumMod, ModuloParam, Trail, Pixelate, Posterise,
+			Threshold, Zoom, Rotation, Spin, PanX, PanY, Kaleido,
+		].into_iter().for_each(|target| {
+			let (low, high) = range(target);
+			assert_eq!(target.clamp(-1e6, plugin), low, "{target:?}");
+			assert_eq!(target.clamp(1e6, plugin), high, "{target:?}");
+			assert_eq!(target.clamp((low + high) / 2.0, plugin), (low + high) / 2.0, "{target:?}");
+		});
+	}
+}
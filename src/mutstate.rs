@@ -1,14 +1,39 @@
 use std::path::Path;
+use std::sync::Arc;
 use crate::save_state::SaveState;
+use crate::palette::Palette;
+
+// a change waiting for the next quantise boundary
+#[derive(Debug)]
+pub enum Launch {
+	Func(usize),
+	Preset(SaveState),
+}
 
 #[derive(Default, Debug)]
 pub struct MutState {
 	pub is_backwards:       bool,
 	pub is_reset:           bool,
+	// a reset for just the next frame
+	pub is_reset_once:      bool,
 	pub is_saving_preset:   bool,
 	pub is_listening_midi:  bool,
 	pub is_listening_keys:  bool,
-	pub plugins:            Vec<crate::loading::Plugin>,
+	pub is_quantise_held:   bool,
+	pub is_taking_screenshot: bool,
+	pub is_showing_hud:     bool,
+	// status, data1, data2 of the last controller message
+	pub last_midi:          Option<[u8; 3]>,
+	pub plugins:            Vec<Arc<crate::loading::Plugin>>,
+	pub palettes:           Vec<Palette>,
+	pub clock:              crate::clock::Clock,
+	pub quantise:           u8,
+	pub pending_launch:     Option<(f32, Launch)>,
+	pub window:             [f32; 2],
+	// what every `onset_every`th detected onset sets off
+	pub onset_actions:      Vec<crate::onset::Action>,
+	pub onset_every:        u32,
+	pub onset_count:        u32,
 
 	pub controller_name:    String,
 	pub save_state:         SaveState,
@@ -59,4 +84,64 @@ impl MutState {
 
 		self.save_state.active_func = afn;
 	}
+
+	pub fn onset(&mut self) {
+		self.onset_count += 1;
+		if self.onset_actions.is_empty() || !self.onset_count.is_multiple_of(self.onset_every.max(1)) { return; }
+
+		self.onset_actions.clone().iter().for_each(|action| action.apply(self));
+	}
+
+	pub fn palette(&self) -> Option<&Palette> {
+		self.palettes.iter()
+			.find(|p| p.name == self.save_state.palette)
+			.or(self.palettes.first())
+	}
+
+	pub fn step_palette(&mut self, step: isize) {
+		if self.palettes.is_empty() { return; }
+
+		let current = self.palettes.iter()
+			.position(|p| p.name == self.save_state.palette)
+			.unwrap_or(0);
+
+		let next = (current as isize + step).rem_euclid(self.palettes.len() as isize) as usize;
+		self.save_state.palette = self.palettes[next].name.clone();
+		println!("[MAIN]: palette {:?}", self.save_state.palette);
+	}
+
+	// queue up a function or preset change for the next beat/bar boundary
+	// launches right away when not quantising or when there's no clock to follow
+	pub fn launch(&mut self, launch: Launch) {
+		if self.quantise == 0 || self.is_quantise_held || !self.clock.is_synced() {
+			self.apply_launch(launch);
+			return;
+		}
+
+		let q  = self.quantise as f32;
+		let at = ((self.clock.beats() / q).floor() + 1.0) * q;
+		println!("[MAIN]: launch {:?} queued for beat {:?}", launch, at);
+		self.pending_launch = Some((at, launch));
+	}
+
+	pub fn update_launch(&mut self) {
+		let Some((at, _)) = self.pending_launch else { return; };
+		let beats = self.clock.beats();
+
+		// a start or song position jumping back counts as a boundary too
+		let jumped_back = beats < at - self.quantise as f32;
+
+		if beats >= at || jumped_back || !self.clock.is_synced() {
+			if let Some((_, launch)) = self.pending_launch.take() {
+				self.apply_launch(launch);
+			}
+		}
+	}
+
+	fn apply_launch(&mut self, launch: Launch) {
+		match launch {
+			Launch::Func(afn)      => self.set_active_func(afn),
+			Launch::Preset(preset) => self.save_state = preset,
+		}
+	}
 }
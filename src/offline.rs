@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+
+use crate::args::RenderArgs;
+use crate::audio_device::AudioConfig;
+use crate::audio_file::AudioFile;
+use crate::audio_processor::AudioProcessor;
+use crate::frame::{Frame, Input, Patch};
+use crate::loading::Plugin;
+use crate::modulation::Ranges;
+use crate::palette::Palette;
+use crate::save_state::SaveState;
+use crate::video::{AviWriter, GifWriter, Y4mWriter};
+
+// picked by the extension of --out, anything else is a directory of pngs
+enum Output {
+	Png(PathBuf),
+	Y4m(Y4mWriter),
+	Avi(AviWriter),
+	Gif(GifWriter),
+}
+
+impl Output {
+	fn create(opts: &RenderArgs) -> Result<Self, Box<dyn std::error::Error>> {
+		let (out, w, h, fps) = (&opts.out, opts.width, opts.height, opts.fps);
+
+		Ok(match out.extension().and_then(|e| e.to_str()) {
+			Some("y4m") => Self::Y4m(Y4mWriter::create(out, w, h, fps)?),
+			Some("avi") => Self::Avi(AviWriter::create(out, w, h, fps)?),
+			Some("gif") => Self::Gif(GifWriter::create(out, w, h, fps)?),
+			_ => {
+				std::fs::create_dir_all(out)?;
+				Self::Png(out.clone())
+			},
+		})
+	}
+
+	fn write(&mut self, n: usize, width: u32, height: u32, rgb: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+		match self {
+			Self::Png(dir) => write_png(dir.join(format!("frame_{:05}.png", n)), width, height, rgb)?,
+			Self::Y4m(y4m) => y4m.write_frame(rgb)?,
+			Self::Avi(avi) => avi.write_frame(rgb)?,
+			Self::Gif(gif) => gif.write_frame(rgb)?,
+		}
+		Ok(())
+	}
+
+	fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+		match self {
+			Self::Png(_)   => (),
+			Self::Y4m(y4m) => y4m.finish()?,
+			Self::Avi(avi) => avi.finish()?,
+			Self::Gif(gif) => gif.finish()?,
+		}
+		Ok(())
+	}
+}
+
+// renders without a window or any audio/midi devices, straight from the plugin pipeline
+pub fn render(opts: &RenderArgs) {
+	let mut plugins = Vec::new();
+	Plugin::load_dir(&*crate::PLUGIN_PATH, &mut plugins);
+
+	let plugin = match &opts.plugin {
+		Some(name) => plugins.iter().find(|p| p.is_named(name)),
+		None       => plugins.first(),
+	};
+
+	let Some(plugin) = plugin else {
+		eprintln!("[RENDER]: plugin {:?} not found in {:?}", opts.plugin, &*crate::PLUGIN_PATH);
+		std::process::exit(1);
+	};
+
+	let mut ss = match &opts.preset {
+		Some(path) => SaveState::from_file(path).unwrap_or_else(|e| {
+			eprintln!("[RENDER]: Error reading preset {:?}: {e}", path);
+			std::process::exit(1);
+		}),
+		None => SaveState::default(),
+	};
+
+	let palettes = Palette::load_dir(&*crate::PALETTES_DIR);
+	let palette  = palettes.iter()
+		.find(|p| p.name == ss.palette)
+		.unwrap_or(&palettes[0]);
+
+	let mut output = Output::create(opts).unwrap_or_else(|e| {
+		eprintln!("[RENDER]: Error creating {:?}: {e}", opts.out);
+		std::process::exit(1);
+	});
+
+	// a bpm makes time run like it would following a midi clock at that tempo
+	let bpm     = opts.bpm.unwrap_or(crate::clock::REFERENCE_BPM);
+	let dt      = bpm / crate::clock::REFERENCE_BPM / opts.fps;
+	let mut pool = scoped_threadpool::Pool::new(*crate::RENDER_THREADS as u32);
+
+	// a loop renders a quarter of its length again on the end
+	// and crossfades that over the start so the last frame leads back into the first
+	let (frames, fade) = match opts.loop_beats {
+		Some(beats) => {
+			let frames = (beats * 60.0 / bpm * opts.fps).round() as usize;
+			(frames, frames / 4)
+		},
+		None => ((opts.duration * opts.fps).round() as usize, 0),
+	};
+
+	println!("[RENDER]: {:?} frames of {:?} at {}x{} into {:?}", frames, plugin.name, opts.width, opts.height, opts.out);
+
+	// the audio file, if there is one, runs along with the frames as fast as they render
+	let mut audio = crate::args::ARGS.audio_file.as_ref().map(|path| {
+		let file = AudioFile::open(path).unwrap_or_else(|e| {
+			eprintln!("[RENDER]: Error reading audio file {:?}: {e}", path);
+			std::process::exit(1);
+		});
+		let cfg    = AudioConfig::from_file(&*crate::AUDIO_CONF_FILE);
+		let mut ap = AudioProcessor::new(file.sample_rate, cfg.fft);
+		ap.set_envelope(ss.decay_factor, ss.decay_param);
+		ap.set_bands(cfg.bands);
+		ap.set_gain(cfg.gain);
+		ap.set_channels(cfg.downmix, cfg.stereo);
+		(file, ap)
+	});
+
+	let mut frame   = Frame::new(crate::renderer::GRID, crate::renderer::GRID);
+	let mut trail   = Vec::new();
+	let mut time    = opts.start;
+	let mut spin    = opts.spin_angle;
+	let mut head    = Vec::with_capacity(fade);
+	let mut written = 0;
+
+	// routes move a copy of the preset each frame, never the preset itself
+	let base          = ss.clone();
+	let mut modulator = crate::modulation::Modulator::default();
+
+	for n in 0..frames + fade {
+		let (mags, sides, bin_hz, bands, note) = match audio.as_mut() {
+			Some((file, ap)) => {
+				// the frames up to where this one ends, so none are heard twice or skipped
+				let at   = |n: usize| (n as f64 / opts.fps as f64 * file.sample_rate as f64) as usize;
+				let from = at(n);
+				ap.add_samples(&file.frames(from, at(n + 1) - from).collect::<Vec<_>>());
+				ss = base.clone();
+				if ss.is_fft { modulator.apply(&mut ss, ap, 1.0 / opts.fps, Ranges::from(&**plugin)); }
+				let note  = ap.pitch_class().filter(|_| ss.is_fft);
+				// like live, plugins only get bands with fft on
+				let bands = if ss.is_fft { ap.band_energies() } else { Vec::new() };
+				(ap.get_magnitudes(), ap.side_magnitudes(), ap.bin_hz(), bands, note)
+			},
+			None => (Vec::new(), Vec::new(), 0.0, Vec::new(), None),
+		};
+
+		let input = Input {
+			patch:      Some(&**plugin as &dyn Patch),
+			save_state: &ss,
+			palette:    ss.follow_key.palette(note, &palettes).unwrap_or(palette),
+			hue_shift:  ss.follow_key.shift(note),
+			mags:       &mags,
+			sides:      &sides,
+			bin_hz,
+			bands:      &bands,
+			time,
+			step:       dt,
+			spin,
+			reset:      false,
+			size:       [opts.width as f32, opts.height as f32],
+		};
+
+		frame.evaluate(&input, &mut pool);
+		crate::effects::apply(&ss, &mut frame.cells, frame.width, &mut trail);
+		time = input.end_time(frame.cells.len());
+		spin = (spin + ss.spin * dt).rem_euclid(1.0);
+
+		// the start of a loop is held back until the end has been rendered to fade into it
+		// so the written loop starts where the fade ends, it's a loop after all
+		let out = match n {
+			n if n < fade   => { head.push(frame.cells.clone()); continue; },
+			n if n < frames => frame.clone(),
+			n => {
+				let start = &head[n - frames];
+				let mix   = (n - frames) as f32 / fade as f32;
+
+				let mut out = frame.clone();
+				out.cells.iter_mut().zip(start).for_each(|(end, start)| {
+					end.iter_mut().zip(start).for_each(|(e, s)| *e += (s - *e) * mix);
+				});
+				out
+			},
+		};
+
+		output.write(written, opts.width, opts.height, &out.to_rgb8(opts.width, opts.height))
+			.unwrap_or_else(|e| {
+				eprintln!("[RENDER]: Error writing frame {:?}: {e}", written);
+				std::process::exit(1);
+			});
+		written += 1;
+	}
+
+	output.finish().unwrap_or_else(|e| {
+		eprintln!("[RENDER]: Error finishing {:?}: {e}", opts.out);
+		std::process::exit(1);
+	});
+
+	println!("[RENDER]: done");
+}
+
+pub fn write_png(path: impl AsRef<Path>, width: u32, height: u32, rgb: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+	let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+	let mut encoder = png::Encoder::new(file, width, height);
+	encoder.set_color(png::ColorType::Rgb);
+	encoder.set_depth(png::BitDepth::Eight);
+
+	let mut writer = encoder.write_header()?;
+	writer.write_image_data(rgb)?;
+
+	Ok(())
+}
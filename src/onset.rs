@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use crate::mutstate::{Launch, MutState};
+
+// what a detected onset does, the same things the controller buttons do
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+	// jumps the visual time back to the start for a frame
+	Reset,
+	Backwards,
+	NextFunc,
+	PrevFunc,
+	PaletteNext,
+	Invert,
+	Mirror,
+	Screenshot,
+	// recalls the preset saved on this cc of the current controller
+	Preset(u8),
+}
+
+impl Action {
+	pub fn apply(&self, ms: &mut MutState) {
+		match self {
+			Self::Reset       => ms.is_reset_once = true,
+			Self::Backwards   => ms.is_backwards = !ms.is_backwards,
+			Self::NextFunc    => ms.launch(Launch::Func((ms.save_state.active_func + 1) % ms.plugins.len().max(1))),
+			Self::PrevFunc    => ms.launch(Launch::Func((ms.save_state.active_func + ms.plugins.len().max(1) - 1) % ms.plugins.len().max(1))),
+			Self::PaletteNext => ms.step_palette(1),
+			Self::Invert      => ms.save_state.invert = !ms.save_state.invert,
+			Self::Mirror      => ms.save_state.mirror = ms.save_state.mirror.next(),
+			Self::Screenshot  => ms.is_taking_screenshot = true,
+			Self::Preset(cc)  => {
+				let preset = ms.preset_map.iter().find(|(c, _)| c == &ms.controller_name)
+					.and_then(|(_, presets)| presets.iter().find(|p| p.cc == *cc))
+					.cloned();
+
+				match preset {
+					Some(preset) => ms.launch(Launch::Preset(preset)),
+					None         => eprintln!("[ONSET]: no preset on cc {:?} for {:?}", cc, ms.controller_name),
+				}
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(default)]
+pub struct OnsetConfig {
+	// how many times the recent average the flux has to jump to count
+	pub sensitivity:     f32,
+	// flux that never counts however quiet it's been, keeps noise in silence from triggering
+	pub floor:           f32,
+	pub min_interval_ms: f32,
+	// how far back the average goes
+	pub history_ms:      f32,
+	// run on every nth onset, 4 would be every bar of four on the floor kicks
+	pub every:           u32,
+	pub actions:         Vec<Action>,
+}
+
+impl Default for OnsetConfig {
+	fn default() -> Self {
+		Self {
+			sensitivity:     1.5,
+			floor:           0.01,
+			min_interval_ms: 120.0,
+			history_ms:      1000.0,
+			every:           1,
+			actions:         Vec::new(),
+		}
+	}
+}
+
+// spectral flux, how much the spectrum got louder since the last fft, against an adaptive threshold
+#[derive(Debug, Clone)]
+pub struct Detector {
+	cfg:      OnsetConfig,
+	prev:     Vec<f32>,
+	history:  VecDeque<f32>,
+	since_ms: f32,
+	// flux over the threshold, above 1 is an onset
+	pub strength: f32,
+}
+
+impl Detector {
+	pub fn new(cfg: OnsetConfig) -> Self {
+		Self { cfg, prev: Vec::new(), history: VecDeque::new(), since_ms: f32::INFINITY, strength: 0.0 }
+	}
+
+	pub fn config(&self) -> &OnsetConfig {
+		&self.cfg
+	}
+
+	// takes the linear magnitudes of one fft and dt_ms since the last, true when it starts an onset
+	pub fn process(&mut self, mags: &[f32], dt_ms: f32) -> bool {
+		if self.prev.len() != mags.len() {
+			self.prev = vec![0.0; mags.len()];
+		}
+
+		// log compressed so quiet bins count too, only increases
+		let flux = mags.iter().zip(self.prev.iter_mut()).map(|(mag, prev)| {
+			let now  = (1.0 + 100.0 * mag).ln();
+			let rise = (now - *prev).max(0.0);
+			*prev = now;
+			rise
+		}).sum::<f32>() / mags.len().max(1) as f32;
+
+		let mean = self.history.iter().sum::<f32>() / self.history.len().max(1) as f32;
+
+		self.history.push_back(flux);
+		let len = ((self.cfg.history_ms / dt_ms.max(0.001)).ceil() as usize).max(1);
+		while self.history.len() > len {
+			self.history.pop_front();
+		}
+
+		let threshold = (mean * self.cfg.sensitivity).max(self.cfg.floor).max(f32::EPSILON);
+		self.strength = flux / threshold;
+		self.since_ms += dt_ms;
+
+		let onset = flux > threshold && self.since_ms >= self.cfg.min_interval_ms;
+		if onset { self.since_ms = 0.0; }
+		onset
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const DT_MS: f32 = 10.0;
+
+	#[test]
+	fn a_burst_after_silence_is_an_onset() {
+		let mut detector = Detector::new(OnsetConfig::default());
+		let quiet = vec![0.0; 64];
+		let loud  = vec![0.5; 64];
+
+		(0..50).for_each(|_| assert!(!detector.process(&quiet, DT_MS)));
+		assert!(detector.process(&loud, DT_MS));
+		assert!(detector.strength > 1.0);
+
+		// holding the same level doesn't rise, so it isn't another one
+		assert!(!detector.process(&loud, DT_MS));
+	}
+
+	#[test]
+	fn onsets_are_at_least_min_interval_apart() {
+		let mut detector = Detector::new(OnsetConfig { min_interval_ms: 100.0, ..Default::default() });
+		let quiet = vec![0.0; 64];
+		let loud  = vec![0.5; 64];
+
+		assert!(detector.process(&loud, DT_MS));
+		assert!(!detector.process(&quiet, DT_MS));
+		// 20 ms on, too soon
+		assert!(!detector.process(&loud, DT_MS));
+
+		(0..10).for_each(|_| { detector.process(&quiet, DT_MS); });
+		assert!(detector.process(&loud, DT_MS));
+	}
+}
@@ -0,0 +1,211 @@
+use std::path::Path;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+struct Stop {
+	pos:   f32,
+	color: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PaletteFile {
+	stops: Vec<Stop>,
+}
+
+// what the note the audio is in does to the colours, with fft on
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FollowKey {
+	#[default]
+	Off,
+	// turns the palette round, keys a fifth apart get neighbouring colours
+	Hue,
+	// the note picks the palette, C the first loaded one, C# the next and so on
+	Palette,
+}
+
+impl FollowKey {
+	// how far round to turn the palette for the note
+	pub fn shift(self, note: Option<usize>) -> f32 {
+		match (self, note) {
+			(Self::Hue, Some(note)) => (note * 7 % 12) as f32 / 12.0,
+			_ => 0.0,
+		}
+	}
+
+	pub fn palette(self, note: Option<usize>, palettes: &[Palette]) -> Option<&Palette> {
+		match (self, note) {
+			(Self::Palette, Some(note)) if !palettes.is_empty() => Some(&palettes[note % palettes.len()]),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Palette {
+	pub name: String,
+	// sorted by position, empty means the full hue wheel
+	stops:    Vec<(f32, [f32; 3])>,
+}
+
+impl Palette {
+	fn builtin(name: &str, stops: &[(f32, u32)]) -> Self {
+		Self {
+			name:  String::from(name),
+			stops: stops.iter().map(|&(pos, hex)| (pos, [
+				((hex >> 16) & 0xFF) as f32 / 255.0,
+				((hex >> 8)  & 0xFF) as f32 / 255.0,
+				( hex        & 0xFF) as f32 / 255.0,
+			])).collect(),
+		}
+	}
+
+	// built-ins always come first, "hue" being the first is what a
+	// save state without a palette falls back to
+	pub fn builtins() -> Vec<Self> {
+		vec![
+			Self { name: String::from("hue"), stops: Vec::new() },
+			Self::builtin("fire",  &[(0.0, 0x1A0000), (0.3, 0xB31200), (0.6, 0xFF8C00), (0.85, 0xFFE680), (1.0, 0x1A0000)]),
+			Self::builtin("ocean", &[(0.0, 0x001233), (0.35, 0x0077B6), (0.65, 0x48CAE4), (0.85, 0xCAF0F8), (1.0, 0x001233)]),
+			Self::builtin("neon",  &[(0.0, 0xFF00A0), (0.33, 0x00F0FF), (0.66, 0xB4FF00), (1.0, 0xFF00A0)]),
+			Self::builtin("mono",  &[(0.0, 0x000000), (0.5, 0xFFFFFF), (1.0, 0x000000)]),
+		]
+	}
+
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+		let path = path.as_ref();
+		let file: PaletteFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+
+		let mut stops = file.stops.iter()
+			.map(|s| Ok((s.pos.clamp(0.0, 1.0), parse_hex(&s.color)?)))
+			.collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+		if stops.is_empty() {
+			return Err(format!("palette {:?} has no stops", path).into());
+		}
+
+		stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+		Ok(Self {
+			name: path.file_stem()
+				.and_then(|s| s.to_str())
+				.map(String::from)
+				.ok_or("bad palette file name")?,
+			stops,
+		})
+	}
+
+	// built-ins followed by every palette file in the directory sorted by name
+	pub fn load_dir(path: impl AsRef<Path>) -> Vec<Self> {
+		let mut palettes = Self::builtins();
+
+		let Ok(dir) = std::fs::read_dir(path.as_ref()) else { return palettes; };
+
+		let mut files = dir
+			.filter_map(|entry| {
+				let path = entry.ok()?.path();
+				(path.is_file() && path.extension().is_some_and(|e| e == "toml")).then_some(path)
+			})
+			.collect::<Vec<_>>();
+
+		files.sort();
+
+		palettes.extend(files.iter().filter_map(|path| Self::from_file(path)
+			.inspect_err(|e| eprintln!("[PALETTE]: Error reading palette file {:?}: {e}", path))
+			.ok()));
+
+		println!("[PALETTE]: loaded {:?}", palettes.iter().map(|p| &p.name).collect::<Vec<_>>());
+
+		palettes
+	}
+
+	// map a plugin output onto the palette, wrapping like the hue wheel does
+	pub fn sample(&self, t: f32) -> [f32; 3] {
+		let t = if t.is_finite() { t.rem_euclid(1.0) } else { 0.0 };
+
+		if self.stops.is_empty() {
+			return hsl_to_rgb(t, 1.0, 0.5);
+		}
+
+		let next = self.stops.iter().position(|(pos, _)| *pos >= t);
+
+		match next {
+			Some(0)    => self.stops[0].1,
+			None       => self.stops[self.stops.len() - 1].1,
+			Some(i)    => {
+				let (p0, c0) = self.stops[i - 1];
+				let (p1, c1) = self.stops[i];
+				let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 0.0 };
+				[
+					c0[0] + (c1[0] - c0[0]) * f,
+					c0[1] + (c1[1] - c0[1]) * f,
+					c0[2] + (c1[2] - c0[2]) * f,
+				]
+			},
+		}
+	}
+
+	// the palette colour at the given saturation and lightness
+	// 1.0 and 0.5 are the palette colour as is, like full saturation and the middle of hsl lightness
+	pub fn color(&self, t: f32, sat: f32, lum: f32) -> [f32; 3] {
+		if self.stops.is_empty() {
+			let t = if t.is_finite() { t.rem_euclid(1.0) } else { 0.0 };
+			return hsl_to_rgb(t, sat.clamp(0.0, 1.0), lum);
+		}
+
+		let (h, s, l) = rgb_to_hsl(self.sample(t));
+		hsl_to_rgb(h, (s * sat).clamp(0.0, 1.0), (l * lum / 0.5).clamp(0.0, 1.0))
+	}
+}
+
+fn parse_hex(s: &str) -> Result<[f32; 3], Box<dyn std::error::Error>> {
+	let hex = s.trim_start_matches('#');
+	if hex.len() != 6 {
+		return Err(format!("colour {:?} is not #rrggbb", s).into());
+	}
+
+	let v = u32::from_str_radix(hex, 16)?;
+	Ok([
+		((v >> 16) & 0xFF) as f32 / 255.0,
+		((v >> 8)  & 0xFF) as f32 / 255.0,
+		( v        & 0xFF) as f32 / 255.0,
+	])
+}
+
+// hue in turns (0..1) like nannou's hsl
+pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> [f32; 3] {
+	let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+	let h = h.rem_euclid(1.0) * 6.0;
+	let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+	let m = l - c / 2.0;
+
+	let (r, g, b) = match h as u32 {
+		0 => (c, x, 0.0),
+		1 => (x, c, 0.0),
+		2 => (0.0, c, x),
+		3 => (0.0, x, c),
+		4 => (x, 0.0, c),
+		_ => (c, 0.0, x),
+	};
+
+	[r + m, g + m, b + m]
+}
+
+pub fn rgb_to_hsl([r, g, b]: [f32; 3]) -> (f32, f32, f32) {
+	let max = r.max(g).max(b);
+	let min = r.min(g).min(b);
+	let l   = (max + min) / 2.0;
+	let d   = max - min;
+
+	if d == 0.0 { return (0.0, 0.0, l); }
+
+	let s = d / (1.0 - (2.0 * l - 1.0).abs());
+	let h = if max == r {
+		((g - b) / d).rem_euclid(6.0)
+	} else if max == g {
+		(b - r) / d + 2.0
+	} else {
+		(r - g) / d + 4.0
+	};
+
+	(h / 6.0, s, l)
+}
@@ -0,0 +1,147 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::audio_processor::AudioProcessor;
+use crate::frame::{Frame, Input, Patch};
+use crate::modulation::Ranges;
+use crate::mutstate::MutState;
+use crate::palette::Palette;
+use crate::screenshot::{self, Sidecar};
+
+// 6 levels of quadtree subdivisions of the window
+pub const GRID: usize = 64;
+
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+// how many frames to average over for the logupdate frame time
+const STATS_FRAMES: u32 = 120;
+
+const UPPER_TIME_LIMIT: f32 = 524288.0;
+const LOWER_TIME_LIMIT: f32 = -524288.0;
+
+// evaluates frames on its own thread and hands finished ones to the view
+// through the returned front buffer so drawing never waits on plugins
+pub fn spawn(ms: Arc<Mutex<MutState>>, ap: Arc<Mutex<AudioProcessor>>) -> Arc<Mutex<Frame>> {
+	let front  = Arc::new(Mutex::new(Frame::default()));
+	let front_ = front.clone();
+
+	std::thread::spawn(move || run(&ms, &ap, &front_));
+
+	front
+}
+
+fn run(ms: &Mutex<MutState>, ap: &Mutex<AudioProcessor>, front: &Mutex<Frame>) {
+	let threads = *crate::RENDER_THREADS;
+	println!("[RENDER]: evaluating the grid on {:?} threads", threads);
+
+	// started once, spawning threads every frame adds up at 60 a second
+	let mut pool = scoped_threadpool::Pool::new(threads as u32);
+
+	let mut back  = Frame::new(GRID, GRID);
+	let mut trail = Vec::new();
+
+	let mut time: f32 = 0.0;
+	let mut spin: f32 = 0.0;
+	let mut last  = Instant::now();
+	let mut stats = (0, Duration::ZERO);
+	let mut mods  = crate::modulation::Modulator::default();
+	// the note heard by the last frame, for picking this frame's palette without holding both locks
+	let mut note  = None;
+
+	loop {
+		let start = Instant::now();
+		let real_dt = start.duration_since(last).as_secs_f32();
+		last = start;
+
+		// take what this frame needs and let go of the lock before evaluating
+		let (mut ss, plugin, palette, size, dt, backwards, reset, shot) = {
+			let mut ms = ms.lock().unwrap();
+
+			if !(LOWER_TIME_LIMIT..UPPER_TIME_LIMIT).contains(&time) {
+				ms.is_backwards = !ms.is_backwards;
+			}
+
+			// follow an external midi clock if one is running
+			if let Some(t) = ms.clock.take_phase() { time = t; }
+
+			let shot = std::mem::take(&mut ms.is_taking_screenshot).then(|| (ms.clock.bpm(), ms.clock.beats()));
+
+			(
+				ms.save_state.clone(),
+				ms.plugins.get(ms.save_state.active_func).cloned(),
+				ms.save_state.follow_key.palette(note, &ms.palettes).or(ms.palette()).cloned()
+					.unwrap_or_else(|| Palette::builtins().remove(0)),
+				ms.window,
+				real_dt * ms.clock.rate(),
+				ms.is_backwards,
+				ms.is_reset || std::mem::take(&mut ms.is_reset_once),
+				shot,
+			)
+		};
+
+		let (mags, sides, bin_hz, bands) = {
+			let mut ap = ap.lock().unwrap();
+			ap.set_envelope(ss.decay_factor, ss.decay_param);
+			if ss.is_fft { mods.apply(&mut ss, &ap, real_dt, plugin.as_deref().map(Ranges::from).unwrap_or_default()); }
+			note = ap.pitch_class().filter(|_| ss.is_fft);
+			// nothing's fed to the fft with it off, so the bands would hold whatever they were last
+			let bands = if ss.is_fft { ap.band_energies() } else { Vec::new() };
+			(ap.get_magnitudes(), ap.side_magnitudes(), ap.bin_hz(), bands)
+		};
+
+		spin = (spin + ss.spin * dt).rem_euclid(1.0);
+
+		let input = Input {
+			patch:      plugin.as_deref().map(|p| p as &dyn Patch),
+			save_state: &ss,
+			palette:    &palette,
+			hue_shift:  ss.follow_key.shift(note),
+			mags:       &mags,
+			sides:      &sides,
+			bin_hz,
+			bands:      &bands,
+			time,
+			step:       if backwards { -dt } else { dt },
+			spin,
+			reset,
+			size,
+		};
+
+		back.evaluate(&input, &mut pool);
+		crate::effects::apply(&ss, &mut back.cells, back.width, &mut trail);
+
+		if let Some((bpm, beats)) = shot {
+			let sidecar = Sidecar {
+				plugin: plugin.as_ref().map_or(String::new(), |p| p.name.clone()),
+				time,
+				spin_angle: spin,
+				bpm,
+				beats,
+			};
+			let frame = back.clone();
+			let ss    = ss.clone();
+
+			// encoding a png takes a while so don't hold up the next frame
+			std::thread::spawn(move || {
+				screenshot::save(&*crate::SCREENSHOT_DIR, &frame, size[0] as u32, size[1] as u32, &ss, &sidecar)
+					.unwrap_or_else(|e| eprintln!("[SCREENSHOT]: Error saving screenshot: {e}"));
+			});
+		}
+
+		time = input.end_time(back.cells.len());
+
+		std::mem::swap(&mut *front.lock().unwrap(), &mut back);
+
+		let elapsed = start.elapsed();
+		if crate::args::ARGS.log_update {
+			stats.0 += 1;
+			stats.1 += elapsed;
+			if stats.0 == STATS_FRAMES {
+				println!("[RENDER]: average frame time {:?}", stats.1 / STATS_FRAMES);
+				stats = (0, Duration::ZERO);
+			}
+		}
+
+		std::thread::sleep(FRAME_TIME.saturating_sub(elapsed));
+	}
+}
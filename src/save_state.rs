@@ -5,16 +5,84 @@ pub struct SaveState {
 	pub is_fft:            bool,
 	pub current_intensity: f32,
 	pub time_dialation:    f32,
+	// attack of the spectrum envelope in ms
 	pub decay_factor:      f32,
 	pub lum_mod:           f32,
 	pub modulo_param:      f32,
+	// release of the spectrum envelope in ms
 	pub decay_param:       f32,
+	// name of the palette, empty falls back to the hue wheel
+	#[serde(default)]
+	pub palette:           String,
+	// turn or swap the palette by the note the audio is in
+	#[serde(default)]
+	pub follow_key:        crate::palette::FollowKey,
+
+	// post effects
+	#[serde(default)]
+	pub effects:           crate::effects::EffectChain,
+	#[serde(default)]
+	pub trail:             f32,
+	#[serde(default)]
+	pub pixelate:          f32,
+	#[serde(default)]
+	pub posterise:         f32,
+	#[serde(default)]
+	pub threshold:         f32,
+	#[serde(default)]
+	pub invert:            bool,
+
+	// coordinate transform before the plugin
+	#[serde(default)]
+	pub zoom:              f32,
+	#[serde(default)]
+	pub rotation:          f32,
+	#[serde(default)]
+	pub spin:              f32,
+	#[serde(default)]
+	pub pan_x:             f32,
+	#[serde(default)]
+	pub pan_y:             f32,
+	#[serde(default)]
+	pub mirror:            crate::transform::Mirror,
+	#[serde(default)]
+	pub kaleido:           f32,
+
+	// fft magnitude to lightness and saturation
+	#[serde(default)]
+	pub levels:            crate::levels::Levels,
+
+	// which part of the spectrum lights which cells
+	#[serde(default)]
+	pub spectrum:          crate::spectrum::Spectrum,
+
+	// audio features moving the parameters above, on top of what they're set to
+	#[serde(default)]
+	pub routes:            Vec<crate::modulation::Route>,
 }
 
 impl SaveState {
 	pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
 		let file = std::fs::read_to_string(path.as_ref())?;
-		Ok(toml::from_str(&file)?)
+		Ok(toml::from_str::<Self>(&file)?.migrated())
+	}
+
+	// decay_factor and decay_param used to be 0..1 factors, now they're attack and release in ms
+	// an old preset is moved to the same spot on the controls so it doesn't lose its smoothing
+	fn migrated(mut self) -> Self {
+		let is_old = |v: f32| v > 0.0 && v < 1.0;
+		if !is_old(self.decay_factor) && !is_old(self.decay_param) { return self; }
+
+		eprintln!("[PRESET][WARN]: preset on cc {:?} has decay values from before they were in ms, converting them", self.cc);
+
+		if is_old(self.decay_factor) {
+			self.decay_factor *= crate::midi::ATTACK_MAX_MS;
+		}
+		// the old control went up to 0.9999
+		if is_old(self.decay_param) {
+			self.decay_param = self.decay_param / 0.9999 * crate::midi::RELEASE_MAX_MS;
+		}
+		self
 	}
 
 	pub fn from_dir(path: impl AsRef<std::path::Path>) -> Vec<(String, Vec<SaveState>)> {
@@ -31,7 +99,7 @@ impl SaveState {
 						path.is_file().then_some(path)
 					})
 					.filter_map(|path| std::fs::read_to_string(&path).ok())
-					.map(|file| toml::from_str::<SaveState>(&file))
+					.map(|file| toml::from_str::<SaveState>(&file).map(SaveState::migrated))
 					.collect::<Result<Vec<_>, toml::de::Error>>() 
 					.unwrap_or_else(|e| {
 						eprintln!("[MAIN]: Error reading save_state file: {e}");
@@ -43,3 +111,18 @@ impl SaveState {
 			.collect::<Vec<_>>()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn old_decay_factors_move_to_the_same_spot_in_ms() {
+		let old = SaveState { decay_factor: 0.5, decay_param: 0.9999, ..Default::default() }.migrated();
+		assert_eq!(old.decay_factor, crate::midi::ATTACK_MAX_MS / 2.0);
+		assert_eq!(old.decay_param, crate::midi::RELEASE_MAX_MS);
+
+		let new = SaveState { decay_factor: 0.0, decay_param: 300.0, ..Default::default() }.migrated();
+		assert_eq!((new.decay_factor, new.decay_param), (0.0, 300.0));
+	}
+}
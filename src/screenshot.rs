@@ -0,0 +1,54 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::frame::Frame;
+use crate::save_state::SaveState;
+
+// what else it takes to render the same look again, written next to the save state
+// so the sidecar also loads as a preset with `render --preset`
+#[derive(Debug, serde::Serialize)]
+pub struct Sidecar {
+	pub plugin:     String,
+	// visual time and camera spin of the frame, for `render --start` and `--spin-angle`
+	pub time:       f32,
+	pub spin_angle: f32,
+	pub bpm:        Option<f32>,
+	pub beats:      f32,
+}
+
+// writes <dir>/<utc date>.png and a .toml next to it
+pub fn save(dir: impl AsRef<Path>, frame: &Frame, width: u32, height: u32, ss: &SaveState, sidecar: &Sidecar) -> Result<(), Box<dyn std::error::Error>> {
+	std::fs::create_dir_all(dir.as_ref())?;
+
+	let path = dir.as_ref().join(timestamp(SystemTime::now()));
+
+	crate::offline::write_png(path.with_extension("png"), width, height, &frame.to_rgb8(width, height))?;
+	let mut table = toml::Table::try_from(sidecar)?;
+	table.extend(toml::Table::try_from(ss)?);
+	std::fs::write(path.with_extension("toml"), toml::to_string(&table)?)?;
+
+	println!("[SCREENSHOT]: saved {:?}", path.with_extension("png"));
+	Ok(())
+}
+
+// 2025-03-31_15-59-02_123 in utc, no timezone database around to do local time
+fn timestamp(now: SystemTime) -> String {
+	let since = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+	let secs  = since.as_secs();
+	let days  = (secs / 86400) as i64;
+	let rem   = secs % 86400;
+
+	// days to civil date from http://howardhinnant.github.io/date_algorithms.html
+	let z   = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp  = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year  = yoe + era * 400 + (month <= 2) as i64;
+
+	format!("{:04}-{:02}-{:02}_{:02}-{:02}-{:02}_{:03}",
+		year, month, day, rem / 3600, (rem / 60) % 60, rem % 60, since.subsec_millis())
+}
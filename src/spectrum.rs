@@ -0,0 +1,113 @@
+// where on the grid each part of the spectrum ends up
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+	// the old running index over raw fft bins wrapped by modulo_param
+	#[default]
+	Index,
+	// lows at the bottom row, highs at the top
+	Rows,
+	// lows at the left column, highs at the right
+	Columns,
+	// lows in the centre, highs out in the corners
+	Radial,
+}
+
+// how the band edges are spread between min_hz and max_hz
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scale {
+	#[default]
+	Log,
+	Mel,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Spectrum {
+	pub layout: Layout,
+	pub scale:  Scale,
+	// interpolated across the cells, no matter how many fft bins there are
+	pub bands:  usize,
+	pub min_hz: f32,
+	pub max_hz: f32,
+}
+
+impl Default for Spectrum {
+	fn default() -> Self {
+		Self {
+			layout: Layout::Index,
+			scale:  Scale::Log,
+			bands:  32,
+			min_hz: 30.0,
+			max_hz: 16000.0,
+		}
+	}
+}
+
+fn hz_to_mel(hz: f32) -> f32 { 2595.0 * (1.0 + hz / 700.0).log10() }
+fn mel_to_hz(mel: f32) -> f32 { 700.0 * (10f32.powf(mel / 2595.0) - 1.0) }
+
+impl Spectrum {
+	// frequency at a position 0..1 along the band range
+	fn hz_at(&self, t: f32) -> f32 {
+		let (lo, hi) = (self.min_hz.max(1.0), self.max_hz.max(self.min_hz.max(1.0)));
+		match self.scale {
+			Scale::Log => lo * (hi / lo).powf(t),
+			Scale::Mel => mel_to_hz(hz_to_mel(lo) + (hz_to_mel(hi) - hz_to_mel(lo)) * t),
+		}
+	}
+
+	// the level of each band in dB from fft magnitudes from 0 Hz up, bin_hz apart
+	pub fn bands(&self, mags: &[f32], bin_hz: f32) -> Vec<f32> {
+		let bins = mags.len();
+		if self.layout == Layout::Index || bins == 0 || bin_hz <= 0.0 {
+			return Vec::new();
+		}
+
+		let bands = self.bands.max(1);
+		let bin_at = |hz: f32| (hz / bin_hz).clamp(0.0, (bins - 1) as f32);
+
+		(0..bands).map(|b| {
+			let lo = bin_at(self.hz_at(b as f32 / bands as f32));
+			let hi = bin_at(self.hz_at((b + 1) as f32 / bands as f32));
+			let (first, last) = (lo.ceil() as usize, hi.floor() as usize);
+
+			if first <= last {
+				// average amplitude of the bins inside the band
+				let amp = mags[first..=last].iter().map(|db| 10f32.powf(db / 20.0)).sum::<f32>()
+					/ (last - first + 1) as f32;
+				20.0 * amp.max(1e-8).log10()
+			} else {
+				// narrower than a bin, read between the two closest
+				let at = (lo + hi) / 2.0;
+				let i  = (at.floor() as usize).min(bins - 1);
+				let f  = at - i as f32;
+				mags[i] + (mags[(i + 1).min(bins - 1)] - mags[i]) * f
+			}
+		}).collect()
+	}
+
+	// where a cell sits along the bands 0..1, None for the old index layout
+	pub fn position(&self, col: usize, row: usize, width: usize, height: usize) -> Option<f32> {
+		let u = (col as f32 + 0.5) / width  as f32;
+		let v = (row as f32 + 0.5) / height as f32;
+
+		match self.layout {
+			Layout::Index   => None,
+			Layout::Rows    => Some(v),
+			Layout::Columns => Some(u),
+			Layout::Radial  => Some(((u * 2.0 - 1.0).hypot(v * 2.0 - 1.0) / std::f32::consts::SQRT_2).min(1.0)),
+		}
+	}
+
+	// a band level at a position, blending the neighbouring bands
+	pub fn sample(bands: &[f32], t: f32) -> Option<f32> {
+		if bands.is_empty() { return None; }
+
+		let at = t.clamp(0.0, 1.0) * (bands.len() - 1) as f32;
+		let i  = at.floor() as usize;
+		let j  = (i + 1).min(bands.len() - 1);
+		Some(bands[i] + (bands[j] - bands[i]) * (at - i as f32))
+	}
+}
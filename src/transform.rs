@@ -0,0 +1,77 @@
+use std::f32::consts::TAU;
+
+use crate::save_state::SaveState;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mirror {
+	#[default]
+	None,
+	X,
+	Y,
+	Both,
+}
+
+impl Mirror {
+	pub fn next(self) -> Self {
+		match self {
+			Self::None => Self::X,
+			Self::X    => Self::Y,
+			Self::Y    => Self::Both,
+			Self::Both => Self::None,
+		}
+	}
+}
+
+// moves the cell coordinates around before the plugin sees them
+// so every plugin gets the same camera moves without knowing about them
+//
+// screen -> mirror -> kaleidoscope fold -> rotate -> zoom -> pan -> plugin
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+	cos:      f32,
+	sin:      f32,
+	scale:    f32,
+	pan:      (f32, f32),
+	mirror:   Mirror,
+	segments: u32,
+}
+
+impl Transform {
+	// spin is how far the save state's spin has turned so far, in turns
+	pub fn new(ss: &SaveState, spin: f32) -> Self {
+		let angle = (ss.rotation + spin) * TAU;
+
+		Self {
+			cos:      angle.cos(),
+			sin:      angle.sin(),
+			// zoom is in doublings so 0 is no zoom
+			scale:    (-ss.zoom).exp2(),
+			pan:      (ss.pan_x, ss.pan_y),
+			mirror:   ss.mirror,
+			segments: ss.kaleido.round().max(0.0) as u32,
+		}
+	}
+
+	pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+		let (mut x, mut y) = match self.mirror {
+			Mirror::None => (x, y),
+			Mirror::X    => (x.abs(), y),
+			Mirror::Y    => (x, y.abs()),
+			Mirror::Both => (x.abs(), y.abs()),
+		};
+
+		if self.segments >= 2 {
+			let seg = TAU / self.segments as f32;
+			let r   = x.hypot(y);
+			let mut a = y.atan2(x).rem_euclid(seg);
+			if a > seg / 2.0 { a = seg - a; }
+			(x, y) = (r * a.cos(), r * a.sin());
+		}
+
+		let rx = x * self.cos - y * self.sin;
+		let ry = x * self.sin + y * self.cos;
+
+		(rx * self.scale + self.pan.0, ry * self.scale + self.pan.1)
+	}
+}
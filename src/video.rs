@@ -0,0 +1,281 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+// fps as a ratio, 29.97 comes out as 2997:100
+fn fps_ratio(fps: f32) -> (u32, u32) {
+	let num = (fps * 1000.0).round() as u32;
+	let den = 1000;
+
+	let (mut a, mut b) = (num.max(1), den);
+	while b != 0 { (a, b) = (b, a % b); }
+	(num / a, den / a)
+}
+
+// uncompressed yuv 4:2:0 stream, bt.601 limited range like most players expect
+pub struct Y4mWriter {
+	out:    BufWriter<File>,
+	width:  usize,
+	height: usize,
+}
+
+impl Y4mWriter {
+	pub fn create(path: impl AsRef<Path>, width: u32, height: u32, fps: f32) -> std::io::Result<Self> {
+		let mut out = BufWriter::new(File::create(path)?);
+		let (num, den) = fps_ratio(fps);
+
+		writeln!(out, "YUV4MPEG2 W{width} H{height} F{num}:{den} Ip A1:1 C420jpeg XCOLORRANGE=LIMITED")?;
+
+		Ok(Self { out, width: width as usize, height: height as usize })
+	}
+
+	// rgb bytes top row first
+	pub fn write_frame(&mut self, rgb: &[u8]) -> std::io::Result<()> {
+		let (w, h) = (self.width, self.height);
+		let px = |x: usize, y: usize| {
+			let i = (y * w + x) * 3;
+			(rgb[i] as f32 / 255.0, rgb[i + 1] as f32 / 255.0, rgb[i + 2] as f32 / 255.0)
+		};
+
+		self.out.write_all(b"FRAME\n")?;
+
+		let luma = (0..h)
+			.flat_map(|y| (0..w).map(move |x| (x, y)))
+			.map(|(x, y)| {
+				let (r, g, b) = px(x, y);
+				(16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8
+			})
+			.collect::<Vec<_>>();
+		self.out.write_all(&luma)?;
+
+		// chroma is the average of each 2x2 block, odd edges just use what's there
+		let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+		let mut cb = Vec::with_capacity(cw * ch);
+		let mut cr = Vec::with_capacity(cw * ch);
+
+		for cy in 0..ch {
+			for cx in 0..cw {
+				let block = [(0, 0), (1, 0), (0, 1), (1, 1)]
+					.map(|(dx, dy)| ((cx * 2 + dx).min(w - 1), (cy * 2 + dy).min(h - 1)))
+					.map(|(x, y)| px(x, y));
+
+				let (r, g, b) = block.iter().fold((0.0, 0.0, 0.0), |a, p| (a.0 + p.0 / 4.0, a.1 + p.1 / 4.0, a.2 + p.2 / 4.0));
+
+				cb.push((128.0 - 37.797 * r - 74.203 * g + 112.0   * b).round() as u8);
+				cr.push((128.0 + 112.0  * r - 93.786 * g - 18.214 * b).round() as u8);
+			}
+		}
+
+		self.out.write_all(&cb)?;
+		self.out.write_all(&cr)
+	}
+
+	pub fn finish(mut self) -> std::io::Result<()> {
+		self.out.flush()
+	}
+}
+
+// riff avi with one uncompressed bottom up bgr stream
+// plain avi 1.0 so it tops out at 4GB
+pub struct AviWriter {
+	out:         BufWriter<File>,
+	width:       u32,
+	height:      u32,
+	frames:      u32,
+	// where the frame counts and sizes get patched in once the length is known
+	total_pos:   u64,
+	length_pos:  u64,
+	movi_pos:    u64,
+	index:       Vec<(u32, u32)>,
+}
+
+impl AviWriter {
+	const AVIF_HASINDEX:   u32 = 0x10;
+	const AVIIF_KEYFRAME:  u32 = 0x10;
+
+	fn frame_size(width: u32, height: u32) -> u32 {
+		// rows are padded to 4 bytes
+		((width * 3).div_ceil(4) * 4) * height
+	}
+
+	pub fn create(path: impl AsRef<Path>, width: u32, height: u32, fps: f32) -> std::io::Result<Self> {
+		let mut out = BufWriter::new(File::create(path)?);
+		let (rate, scale) = fps_ratio(fps);
+		let frame_size    = Self::frame_size(width, height);
+
+		out.write_all(b"RIFF")?;
+		out.write_u32::<LittleEndian>(0)?; // patched
+		out.write_all(b"AVI ")?;
+
+		// LIST hdrl = avih + LIST strl (strh + strf)
+		out.write_all(b"LIST")?;
+		out.write_u32::<LittleEndian>(4 + (8 + 56) + (8 + 4 + (8 + 56) + (8 + 40)))?;
+		out.write_all(b"hdrl")?;
+
+		out.write_all(b"avih")?;
+		out.write_u32::<LittleEndian>(56)?;
+		out.write_u32::<LittleEndian>((1_000_000.0 * scale as f32 / rate as f32).round() as u32)?;
+		out.write_u32::<LittleEndian>((frame_size as f32 * rate as f32 / scale as f32) as u32)?;
+		out.write_u32::<LittleEndian>(0)?;
+		out.write_u32::<LittleEndian>(Self::AVIF_HASINDEX)?;
+		let total_pos = out.stream_position()?;
+		out.write_u32::<LittleEndian>(0)?; // total frames, patched
+		out.write_u32::<LittleEndian>(0)?;
+		out.write_u32::<LittleEndian>(1)?; // streams
+		out.write_u32::<LittleEndian>(frame_size)?;
+		out.write_u32::<LittleEndian>(width)?;
+		out.write_u32::<LittleEndian>(height)?;
+		out.write_all(&[0; 16])?;
+
+		out.write_all(b"LIST")?;
+		out.write_u32::<LittleEndian>(4 + (8 + 56) + (8 + 40))?;
+		out.write_all(b"strl")?;
+
+		out.write_all(b"strh")?;
+		out.write_u32::<LittleEndian>(56)?;
+		out.write_all(b"vids")?;
+		out.write_all(b"DIB ")?;
+		out.write_u32::<LittleEndian>(0)?;     // flags
+		out.write_u32::<LittleEndian>(0)?;     // priority + language
+		out.write_u32::<LittleEndian>(0)?;     // initial frames
+		out.write_u32::<LittleEndian>(scale)?;
+		out.write_u32::<LittleEndian>(rate)?;
+		out.write_u32::<LittleEndian>(0)?;     // start
+		let length_pos = out.stream_position()?;
+		out.write_u32::<LittleEndian>(0)?;     // length, patched
+		out.write_u32::<LittleEndian>(frame_size)?;
+		out.write_u32::<LittleEndian>(u32::MAX)?; // quality
+		out.write_u32::<LittleEndian>(0)?;     // sample size
+		out.write_u16::<LittleEndian>(0)?;
+		out.write_u16::<LittleEndian>(0)?;
+		out.write_u16::<LittleEndian>(width as u16)?;
+		out.write_u16::<LittleEndian>(height as u16)?;
+
+		// BITMAPINFOHEADER, positive height means bottom up rows
+		out.write_all(b"strf")?;
+		out.write_u32::<LittleEndian>(40)?;
+		out.write_u32::<LittleEndian>(40)?;
+		out.write_i32::<LittleEndian>(width as i32)?;
+		out.write_i32::<LittleEndian>(height as i32)?;
+		out.write_u16::<LittleEndian>(1)?;
+		out.write_u16::<LittleEndian>(24)?;
+		out.write_u32::<LittleEndian>(0)?; // BI_RGB
+		out.write_u32::<LittleEndian>(frame_size)?;
+		out.write_all(&[0; 16])?;
+
+		let movi_pos = out.stream_position()?;
+		out.write_all(b"LIST")?;
+		out.write_u32::<LittleEndian>(0)?; // patched
+		out.write_all(b"movi")?;
+
+		Ok(Self {
+			out,
+			width,
+			height,
+			frames: 0,
+			total_pos,
+			length_pos,
+			movi_pos,
+			index: Vec::new(),
+		})
+	}
+
+	// rgb bytes top row first
+	pub fn write_frame(&mut self, rgb: &[u8]) -> std::io::Result<()> {
+		let size   = Self::frame_size(self.width, self.height);
+		let stride = (size / self.height) as usize;
+		let row    = self.width as usize * 3;
+
+		// offsets in the index count from the movi fourcc
+		let offset = self.out.stream_position()? - (self.movi_pos + 8);
+		let offset = u32::try_from(offset).map_err(|_| std::io::Error::other("avi is over 4GB"))?;
+		self.index.push((offset, size));
+
+		self.out.write_all(b"00db")?;
+		self.out.write_u32::<LittleEndian>(size)?;
+
+		let mut line = vec![0; stride];
+		for y in (0..self.height as usize).rev() {
+			rgb[y * row..(y + 1) * row].chunks_exact(3)
+				.zip(line.chunks_exact_mut(3))
+				.for_each(|(rgb, bgr)| bgr.copy_from_slice(&[rgb[2], rgb[1], rgb[0]]));
+			self.out.write_all(&line)?;
+		}
+
+		self.frames += 1;
+		Ok(())
+	}
+
+	pub fn finish(mut self) -> std::io::Result<()> {
+		let movi_end = self.out.stream_position()?;
+
+		self.out.write_all(b"idx1")?;
+		self.out.write_u32::<LittleEndian>(self.index.len() as u32 * 16)?;
+		for (offset, size) in &self.index {
+			self.out.write_all(b"00db")?;
+			self.out.write_u32::<LittleEndian>(Self::AVIIF_KEYFRAME)?;
+			self.out.write_u32::<LittleEndian>(*offset)?;
+			self.out.write_u32::<LittleEndian>(*size)?;
+		}
+
+		let end = self.out.stream_position()?;
+
+		let patches = [
+			(4,               end - 8),
+			(self.movi_pos + 4, movi_end - self.movi_pos - 8),
+			(self.total_pos,  self.frames as u64),
+			(self.length_pos, self.frames as u64),
+		];
+
+		for (pos, value) in patches {
+			self.out.seek(SeekFrom::Start(pos))?;
+			self.out.write_u32::<LittleEndian>(u32::try_from(value).map_err(|_| std::io::Error::other("avi is over 4GB"))?)?;
+		}
+
+		self.out.flush()
+	}
+}
+
+// looping animated gif, each frame quantised to its own 256 colour palette
+pub struct GifWriter {
+	out:    gif::Encoder<BufWriter<File>>,
+	width:  u16,
+	height: u16,
+	// gif delays are whole centiseconds so carry the rounding over to the next frame
+	delay:  f32,
+	owed:   f32,
+}
+
+impl GifWriter {
+	// 1 is the best quantisation and slowest, 30 the worst and fastest
+	const SPEED: i32 = 10;
+
+	pub fn create(path: impl AsRef<Path>, width: u32, height: u32, fps: f32) -> Result<Self, Box<dyn std::error::Error>> {
+		let width  = u16::try_from(width).map_err(|_| "gif is at most 65535 wide")?;
+		let height = u16::try_from(height).map_err(|_| "gif is at most 65535 high")?;
+
+		let mut out = gif::Encoder::new(BufWriter::new(File::create(path)?), width, height, &[])?;
+		out.set_repeat(gif::Repeat::Infinite)?;
+
+		Ok(Self { out, width, height, delay: 100.0 / fps, owed: 0.0 })
+	}
+
+	// rgb bytes top row first
+	pub fn write_frame(&mut self, rgb: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+		let mut frame = gif::Frame::from_rgb_speed(self.width, self.height, rgb, Self::SPEED);
+
+		self.owed  += self.delay;
+		frame.delay = self.owed.round() as u16;
+		self.owed  -= frame.delay as f32;
+
+		self.out.write_frame(&frame)?;
+		Ok(())
+	}
+
+	pub fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+		self.out.into_inner()?.flush()?;
+		Ok(())
+	}
+}